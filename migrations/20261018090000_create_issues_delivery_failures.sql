@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE issues_delivery_failures(
+    newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id),
+    subscriber_email TEXT NOT NULL,
+    last_error TEXT NOT NULL,
+    n_attempts INT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issues_id,subscriber_email)
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE deliveries(
+    newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id),
+    subscriber_email TEXT NOT NULL,
+    provider_message_id TEXT,
+    status TEXT NOT NULL,
+    error_code INT,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issues_id,subscriber_email)
+);
+CREATE INDEX deliveries_provider_message_id_idx ON deliveries (provider_message_id);
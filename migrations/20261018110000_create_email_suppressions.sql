@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE email_suppressions(
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL,
+    PRIMARY KEY (email)
+);
+ALTER TABLE subscriptions ADD COLUMN soft_bounces INT NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN delivery_state TEXT NOT NULL DEFAULT 'sending',
+    ADD COLUMN n_cancelled_deliveries INT NOT NULL DEFAULT 0;
+UPDATE newsletter_issues i
+    SET delivery_state = 'completed'
+    WHERE NOT EXISTS (
+        SELECT 1 FROM issues_delivery_queue q
+        WHERE q.newsletter_issues_id = i.newsletter_issues_id
+    );
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN scheduled_for timestamptz,
+    ALTER COLUMN published_at DROP NOT NULL;
+CREATE INDEX newsletter_issues_scheduled_for_idx
+    ON newsletter_issues (scheduled_for)
+    WHERE delivery_state = 'scheduled';
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+UPDATE newsletter_issues
+    SET updated_at = published_at
+    WHERE published_at IS NOT NULL;
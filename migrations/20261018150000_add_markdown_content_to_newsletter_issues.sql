@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN markdown_content TEXT NOT NULL DEFAULT '';
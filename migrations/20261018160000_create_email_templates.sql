@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE email_templates (
+    email_template_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    html_layout TEXT NOT NULL,
+    text_layout TEXT NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_template_id)
+);
+ALTER TABLE newsletter_issues
+    ADD COLUMN email_template_id uuid
+        REFERENCES email_templates (email_template_id) ON DELETE SET NULL;
@@ -0,0 +1,31 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN slug TEXT UNIQUE,
+    ADD COLUMN hidden_from_archive BOOLEAN NOT NULL DEFAULT false;
+
+-- Issues published so far get the slug `slugify` would give them. Titles
+-- used more than once are told apart by the start of the issue id.
+UPDATE newsletter_issues i
+SET slug = s.base || CASE WHEN s.n > 1 THEN '-' || left(i.newsletter_issues_id::TEXT, 8) ELSE '' END
+FROM (
+    SELECT
+        newsletter_issues_id,
+        base,
+        row_number() OVER (PARTITION BY base ORDER BY published_at, newsletter_issues_id) AS n
+    FROM (
+        SELECT
+            newsletter_issues_id,
+            published_at,
+            COALESCE(
+                NULLIF(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+                'issue'
+            ) AS base
+        FROM newsletter_issues
+        WHERE delivery_state <> 'draft'
+    ) b
+) s
+WHERE i.newsletter_issues_id = s.newsletter_issues_id;
+
+CREATE INDEX newsletter_issues_archive_idx
+    ON newsletter_issues (published_at DESC)
+    WHERE NOT hidden_from_archive;
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A') ||
+        setweight(to_tsvector('english', text_content), 'B')
+    ) STORED;
+CREATE INDEX newsletter_issues_search_idx
+    ON newsletter_issues USING GIN (search_vector);
@@ -0,0 +1,25 @@
+-- Add migration script here
+CREATE TABLE lists (
+    list_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    slug TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- `status` follows `subscriptions.status`: a membership is
+-- 'pending_confirmations' until the subscriber clicks their confirmation link.
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    joined_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+
+-- An issue with no target list goes to every confirmed subscriber.
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES lists (list_id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issues_id, list_id)
+);
@@ -0,0 +1,32 @@
+-- Add migration script here
+-- Where a subscriber signed up, e.g. the `source` field of an embedded form.
+ALTER TABLE subscriptions ADD COLUMN signup_source TEXT;
+
+CREATE TABLE tags (
+    tag_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    tag_id uuid NOT NULL REFERENCES tags (tag_id) ON DELETE CASCADE,
+    PRIMARY KEY (subscriber_id, tag_id)
+);
+CREATE INDEX subscriber_tags_tag_id_idx ON subscriber_tags (tag_id);
+
+-- A saved filter over confirmed subscribers. Every condition left NULL
+-- matches everyone; the others must all hold. Signup dates are UTC days,
+-- both ends included.
+CREATE TABLE segments (
+    segment_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    tag_id uuid REFERENCES tags (tag_id),
+    signed_up_from DATE,
+    signed_up_until DATE,
+    signup_source TEXT,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid
+        REFERENCES segments (segment_id) ON DELETE SET NULL;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- The soft bounces already counted, so that a webhook the provider
+-- delivers again does not count twice towards suppression.
+CREATE TABLE soft_bounce_events (
+    provider_message_id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    received_at timestamptz NOT NULL DEFAULT now()
+);
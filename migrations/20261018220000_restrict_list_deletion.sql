@@ -0,0 +1,7 @@
+-- Add migration script here
+-- An issue with no target list goes to every confirmed subscriber, so
+-- deleting a list must never quietly remove it from an issue.
+ALTER TABLE newsletter_issue_lists
+    DROP CONSTRAINT newsletter_issue_lists_list_id_fkey,
+    ADD CONSTRAINT newsletter_issue_lists_list_id_fkey
+        FOREIGN KEY (list_id) REFERENCES lists (list_id) ON DELETE RESTRICT;
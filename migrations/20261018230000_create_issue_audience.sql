@@ -0,0 +1,39 @@
+-- Add migration script here
+-- The subscribers an issue goes to: confirmed, not suppressed, on one of
+-- `audience_list_ids` unless it is empty, and matched by the segment
+-- unless it is NULL. Enqueueing an issue and counting its recipients
+-- beforehand both read it, so that they never disagree.
+CREATE FUNCTION issue_audience(audience_segment_id uuid, audience_list_ids uuid[])
+RETURNS TABLE (subscriber_id uuid, email TEXT)
+LANGUAGE sql STABLE
+AS $$
+    SELECT s.id, s.email
+    FROM subscriptions s
+    WHERE s.status = 'confirmed'
+    AND s.email NOT IN (SELECT email FROM email_suppressions)
+    AND (
+        cardinality(audience_list_ids) = 0
+        OR EXISTS (
+            SELECT 1
+            FROM list_memberships m
+            WHERE m.list_id = ANY(audience_list_ids)
+            AND m.subscriber_id = s.id
+            AND m.status = 'confirmed'
+        )
+    )
+    AND (
+        audience_segment_id IS NULL
+        OR EXISTS (
+            SELECT 1
+            FROM segments g
+            WHERE g.segment_id = audience_segment_id
+            AND (g.tag_id IS NULL OR EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.tag_id = g.tag_id AND t.subscriber_id = s.id
+            ))
+            AND (g.signed_up_from IS NULL OR s.subscribed_at >= g.signed_up_from::timestamp AT TIME ZONE 'UTC')
+            AND (g.signed_up_until IS NULL OR s.subscribed_at < (g.signed_up_until + 1)::timestamp AT TIME ZONE 'UTC')
+            AND (g.signup_source IS NULL OR s.signup_source = g.signup_source)
+        )
+    )
+$$;
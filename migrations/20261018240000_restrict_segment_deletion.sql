@@ -0,0 +1,7 @@
+-- Add migration script here
+-- An issue with no segment goes to its whole audience, so deleting a
+-- segment must never quietly remove it from an issue.
+ALTER TABLE newsletter_issues
+    DROP CONSTRAINT newsletter_issues_segment_id_fkey,
+    ADD CONSTRAINT newsletter_issues_segment_id_fkey
+        FOREIGN KEY (segment_id) REFERENCES segments (segment_id) ON DELETE RESTRICT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Issues sent to a list or a segment are not meant for the public.
+UPDATE newsletter_issues i
+SET hidden_from_archive = true
+WHERE i.segment_id IS NOT NULL
+OR EXISTS (
+    SELECT 1 FROM newsletter_issue_lists l
+    WHERE l.newsletter_issues_id = i.newsletter_issues_id
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Deleting a template must never quietly strip the layout from an issue that
+-- is still to be sent.
+ALTER TABLE newsletter_issues
+    DROP CONSTRAINT newsletter_issues_email_template_id_fkey,
+    ADD CONSTRAINT newsletter_issues_email_template_id_fkey
+        FOREIGN KEY (email_template_id) REFERENCES email_templates (email_template_id) ON DELETE RESTRICT;
@@ -2,18 +2,21 @@ use std::{env, time::Duration};
 
 
 use config::{ConfigError,File};
+use rand::Rng;
 use serde::Deserialize;
 use serde_aux::prelude::*;
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::{postgres::{PgConnectOptions,PgSslMode},ConnectOptions};
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient, startup::HmacSecret};
+use crate::{domain::SubscriberEmail, email_client::{EmailClient, FileTransport, PostmarkTransport, SmtpTransport}, startup::HmacSecret};
 
 #[derive(Deserialize,Clone)]
 pub struct Setting {
     pub database: DatabaseSetting,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub email_webhook: EmailWebhookSettings,
     pub redis_uri: SecretString,
    
 }
@@ -21,19 +24,74 @@ pub struct Setting {
 
 #[derive(Deserialize,Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub kind: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds:u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file: Option<FileTransportSettings>,
+}
+
+/// Which `EmailTransport` the email client delivers through.
+#[derive(Deserialize,Clone,Copy,Debug,Default,PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(Deserialize,Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: String,
+    pub password: SecretString,
+    pub starttls: bool,
+}
+
+#[derive(Deserialize,Clone)]
+pub struct FileTransportSettings {
+    pub directory: String,
 }
 
 impl EmailClientSettings {
     pub fn client(&self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address");
         let timeout = self.timeout();
-        let base_url = self.base_url.clone();
-        let token = self.authorization_token.clone();
-        EmailClient::new(base_url, sender_email, token, timeout)
+        match self.kind {
+            EmailTransportKind::Postmark => {
+                let base_url = self.base_url.clone();
+                let token = self.authorization_token.clone();
+                EmailClient::new(sender_email, PostmarkTransport::new(base_url, token, timeout))
+            }
+            EmailTransportKind::Smtp => {
+                let smtp = self.smtp
+                    .as_ref()
+                    .expect("email_client.smtp must be set when kind is `smtp`");
+                let transport = SmtpTransport::new(
+                    &smtp.host,
+                    smtp.port,
+                    smtp.username.clone(),
+                    smtp.password.clone(),
+                    smtp.starttls,
+                    timeout
+                ).expect("Invalid SMTP relay");
+                EmailClient::new(sender_email, transport)
+            }
+            EmailTransportKind::File => {
+                let file = self.file
+                    .as_ref()
+                    .expect("email_client.file must be set when kind is `file`");
+                std::fs::create_dir_all(&file.directory)
+                    .expect("Failed to create the email output directory");
+                EmailClient::new(sender_email, FileTransport::new(&file.directory))
+            }
+        }
     }
     pub fn sender(&self) -> Result<SubscriberEmail,String> {
         SubscriberEmail::parse(self.sender_email.clone())
@@ -44,6 +102,41 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(Deserialize,Clone,Debug)]
+pub struct IssueDeliverySettings {
+    pub workers: usize,
+    pub batch_size: usize,
+    pub max_retries: u32,
+    pub base_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    pub poll_interval_milliseconds: u64,
+}
+
+impl IssueDeliverySettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    /// Exponential backoff for the given attempt, capped at
+    /// `max_backoff_milliseconds` and with up to 50% random jitter on top.
+    pub fn backoff(&self, n_retries:u32) -> Duration {
+        let delay = self.base_backoff_milliseconds
+            .saturating_mul(2u64.saturating_pow(n_retries))
+            .min(self.max_backoff_milliseconds);
+        let jitter = rand::rng().random_range(0..=delay / 2);
+        Duration::from_millis(delay + jitter)
+    }
+}
+
+/// Credentials the email provider sends with its webhook calls, and when
+/// soft bounces start to count as a hard one.
+#[derive(Deserialize,Clone)]
+pub struct EmailWebhookSettings {
+    pub username: String,
+    pub password: SecretString,
+    pub soft_bounce_threshold: i32,
+}
+
 #[derive(Deserialize,Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -0,0 +1,71 @@
+use super::merge_tag_problems;
+
+/// Where an issue's content goes in a layout.
+pub const CONTENT_SLOT: &str = "{{content}}";
+
+/// The HTML and plain-text layouts of an `email_templates` row.
+#[derive(Debug,Clone)]
+pub struct EmailLayout {
+    pub html_layout:String,
+    pub text_layout:String,
+}
+
+impl EmailLayout {
+    /// Both layouts need a `{{content}}` slot, or the issue would be dropped.
+    /// Any other tag has to be a merge tag.
+    pub fn parse(html_layout:String, text_layout:String) -> Result<EmailLayout,String> {
+        for (name, layout) in [("HTML", &html_layout), ("text", &text_layout)] {
+            if !layout.contains(CONTENT_SLOT) {
+                return Err(format!("The {} layout has no {} slot.", name, CONTENT_SLOT));
+            }
+        }
+        let layout = Self {html_layout, text_layout};
+        match layout.merge_tag_problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(layout),
+        }
+    }
+
+    pub fn merge_tag_problems(&self) -> Vec<String> {
+        let mut problems = merge_tag_problems(&self.html_layout.replace(CONTENT_SLOT, ""), true);
+        problems.extend(merge_tag_problems(&self.text_layout.replace(CONTENT_SLOT, ""), false));
+        problems
+    }
+
+    pub fn wrap(&self, html_content:&str, text_content:&str) -> (String,String) {
+        (
+            self.html_layout.replace(CONTENT_SLOT, html_content),
+            self.text_layout.replace(CONTENT_SLOT, text_content),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+
+    use super::EmailLayout;
+
+    #[test]
+    fn layouts_without_a_content_slot_are_rejected() {
+        assert_err!(EmailLayout::parse("<body></body>".into(), "{{content}}".into()));
+        assert_err!(EmailLayout::parse("<body>{{content}}</body>".into(), "--".into()));
+    }
+
+    #[test]
+    fn layouts_may_only_use_known_merge_tags() {
+        assert!(EmailLayout::parse("{{content}}{{ unsubscribe_url }}".into(), "{{content}}".into()).is_ok());
+        assert_err!(EmailLayout::parse("{{content}}{{ footer }}".into(), "{{content}}".into()));
+    }
+
+    #[test]
+    fn content_is_wrapped_in_both_layouts() {
+        let layout = EmailLayout::parse(
+            "<body>{{content}}</body>".into(),
+            "{{content}}\n--\nThe team".into()
+        ).unwrap();
+        let (html, text) = layout.wrap("<p>Hi</p>", "Hi");
+        assert_eq!(html, "<body><p>Hi</p></body>");
+        assert_eq!(text, "Hi\n--\nThe team");
+    }
+}
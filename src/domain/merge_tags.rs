@@ -0,0 +1,170 @@
+/// The merge tags an issue or a layout may use, as in `{{ subscriber.name }}`.
+/// Any of them takes a fallback: `{{ subscriber.name | default: "friend" }}`.
+pub const MERGE_TAGS: [&str; 4] = ["subscriber.name", "subscriber.email", "unsubscribe_url", "issue.title"];
+
+/// What the merge tags of an issue are replaced with for one recipient.
+#[derive(Debug,Clone,Copy)]
+pub struct MergeFields<'a> {
+    pub subscriber_name:Option<&'a str>,
+    pub subscriber_email:&'a str,
+    pub unsubscribe_url:&'a str,
+    pub issue_title:&'a str,
+}
+
+impl MergeFields<'_> {
+    fn value(&self, name:&str) -> &str {
+        match name {
+            "subscriber.name" => self.subscriber_name.unwrap_or(""),
+            "subscriber.email" => self.subscriber_email,
+            "unsubscribe_url" => self.unsubscribe_url,
+            "issue.title" => self.issue_title,
+            _ => "",
+        }
+    }
+}
+
+struct MergeTag {
+    name:String,
+    default:Option<String>,
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    /// The whole `{{ … }}` and what is between the braces.
+    Tag(&'a str, &'a str),
+    Unclosed(&'a str),
+}
+
+fn pieces(content:&str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        pieces.push(Piece::Text(&rest[..start]));
+        rest = &rest[start..];
+        match rest[2..].find("}}") {
+            Some(end) => {
+                pieces.push(Piece::Tag(&rest[..end + 4], &rest[2..end + 2]));
+                rest = &rest[end + 4..];
+            }
+            None => {
+                pieces.push(Piece::Unclosed(rest));
+                return pieces;
+            }
+        }
+    }
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+/// In an HTML part the tag is HTML text, so `&quot;` stands for a quote.
+fn parse_tag(inner:&str, html:bool) -> Result<MergeTag,String> {
+    let inner = match html {
+        true => htmlescape::decode_html(inner).unwrap_or_else(|_| inner.to_string()),
+        false => inner.to_string(),
+    };
+    let (name, default) = match inner.split_once('|') {
+        None => (inner.trim(), None),
+        Some((name, filter)) => {
+            let default = filter
+                .trim()
+                .strip_prefix("default:")
+                .map(str::trim)
+                .and_then(|d| d.strip_prefix('"'))
+                .and_then(|d| d.strip_suffix('"'))
+                .ok_or_else(|| format!(
+                    "{{{{{}}}}} is not a valid merge tag, a fallback is written | default: \"…\".",
+                    inner
+                ))?;
+            (name.trim(), Some(default.to_string()))
+        }
+    };
+    if !MERGE_TAGS.contains(&name) {
+        return Err(format!("{{{{ {} }}}} is not a known merge tag.", name));
+    }
+    Ok(MergeTag {name: name.to_string(), default})
+}
+
+/// Lists the unknown and malformed merge tags of `content`.
+pub fn merge_tag_problems(content:&str, html:bool) -> Vec<String> {
+    pieces(content)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Text(_) => None,
+            Piece::Tag(_, inner) => parse_tag(inner, html).err(),
+            Piece::Unclosed(_) => Some("A merge tag is never closed with }}.".to_string()),
+        })
+        .collect()
+}
+
+/// Replaces the merge tags of `content`, escaping the values in an HTML part.
+/// Tags that do not parse are left as they are: they are rejected on publish.
+pub fn render_merge_tags(content:&str, fields:&MergeFields, html:bool) -> String {
+    let mut rendered = String::with_capacity(content.len());
+    for piece in pieces(content) {
+        match piece {
+            Piece::Text(text) | Piece::Unclosed(text) => rendered.push_str(text),
+            Piece::Tag(tag, inner) => match parse_tag(inner, html) {
+                Ok(MergeTag {name, default}) => {
+                    let value = match (fields.value(&name), default.as_deref()) {
+                        ("", Some(default)) => default,
+                        (value, _) => value,
+                    };
+                    match html {
+                        true => rendered.push_str(&htmlescape::encode_minimal(value)),
+                        false => rendered.push_str(value),
+                    }
+                }
+                Err(_) => rendered.push_str(tag),
+            },
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_tag_problems, render_merge_tags, MergeFields};
+
+    fn fields(subscriber_name:Option<&str>) -> MergeFields<'_> {
+        MergeFields {
+            subscriber_name,
+            subscriber_email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?a=1&b=2",
+            issue_title: "Issue #1",
+        }
+    }
+
+    #[test]
+    fn tags_are_replaced_with_the_recipient_values() {
+        let content = "Hi {{ subscriber.name }} <{{subscriber.email}}>, {{ issue.title }}: {{ unsubscribe_url }}";
+        assert_eq!(
+            render_merge_tags(content, &fields(Some("Ursula")), false),
+            "Hi Ursula <ursula@example.com>, Issue #1: https://example.com/unsubscribe?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn missing_values_fall_back_to_the_default() {
+        let content = r#"Hi {{ subscriber.name | default: "friend" }}{{ subscriber.name }}!"#;
+        assert_eq!(render_merge_tags(content, &fields(None), false), "Hi friend!");
+        assert_eq!(render_merge_tags(content, &fields(Some("Ursula")), false), "Hi UrsulaUrsula!");
+    }
+
+    #[test]
+    fn html_values_are_escaped_and_html_tags_decoded() {
+        let content = "<p>Hi {{ subscriber.name | default: &quot;friend&quot; }}</p><a href=\"{{ unsubscribe_url }}\">x</a>";
+        assert_eq!(
+            render_merge_tags(content, &fields(Some("<b>Ursula</b>")), true),
+            "<p>Hi &lt;b&gt;Ursula&lt;/b&gt;</p><a href=\"https://example.com/unsubscribe?a=1&amp;b=2\">x</a>"
+        );
+        assert_eq!(render_merge_tags(content, &fields(None), true).get(..12), Some("<p>Hi friend"));
+    }
+
+    #[test]
+    fn unknown_and_malformed_tags_are_reported() {
+        assert!(merge_tag_problems(r#"{{ subscriber.name | default: "x" }} {{issue.title}}"#, false).is_empty());
+        assert_eq!(merge_tag_problems("Hi {{ subscriber.age }}", false), vec!["{{ subscriber.age }} is not a known merge tag."]);
+        assert_eq!(merge_tag_problems("Hi {{ subscriber.name", false), vec!["A merge tag is never closed with }}."]);
+        assert_eq!(merge_tag_problems("{{ issue.title | upper }}", false).len(), 1);
+    }
+}
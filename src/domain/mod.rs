@@ -2,7 +2,13 @@
  mod subscriber_name;
  mod subscriber_email;
  mod new_subscriber;
+ mod email_layout;
+ mod merge_tags;
+ mod segment;
 
 pub use subscriber_name::SubscriberName;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
+pub use email_layout::{EmailLayout, CONTENT_SLOT};
+pub use merge_tags::{merge_tag_problems, render_merge_tags, MergeFields, MERGE_TAGS};
+pub use segment::SegmentFilter;
@@ -0,0 +1,86 @@
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+/// The conditions of a `segments` row. A subscriber is in the segment when
+/// every condition that is set holds.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SegmentFilter {
+    pub tag_id:Option<Uuid>,
+    /// The first UTC day of the signup range.
+    pub signed_up_from:Option<NaiveDate>,
+    /// The last UTC day of the signup range, included.
+    pub signed_up_until:Option<NaiveDate>,
+    pub signup_source:Option<String>,
+}
+
+impl SegmentFilter {
+    /// Reads the fields of a segment form, where empty means no condition.
+    /// Dates are `YYYY-MM-DD`, as sent by a `date` input.
+    pub fn parse(
+        tag_id:&str,
+        signed_up_from:&str,
+        signed_up_until:&str,
+        signup_source:&str
+    ) -> Result<SegmentFilter,String> {
+        let tag_id = match tag_id.trim() {
+            "" => None,
+            id => Some(Uuid::parse_str(id).map_err(|_| format!("{} is not a valid tag.", id))?),
+        };
+        let signed_up_from = parse_day(signed_up_from)?;
+        let signed_up_until = parse_day(signed_up_until)?;
+        if matches!((signed_up_from, signed_up_until), (Some(from), Some(until)) if from > until) {
+            return Err("The signup range ends before it starts.".to_string());
+        }
+        let signup_source = match signup_source.trim() {
+            "" => None,
+            source => Some(source.to_string()),
+        };
+        let filter = SegmentFilter {tag_id, signed_up_from, signed_up_until, signup_source};
+        if filter == SegmentFilter::everyone() {
+            return Err("A segment needs at least one condition.".to_string());
+        }
+        Ok(filter)
+    }
+
+    fn everyone() -> SegmentFilter {
+        SegmentFilter {tag_id: None, signed_up_from: None, signed_up_until: None, signup_source: None}
+    }
+}
+
+fn parse_day(s:&str) -> Result<Option<NaiveDate>,String> {
+    match s.trim() {
+        "" => Ok(None),
+        s => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid date.", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use claim::assert_err;
+
+    use super::SegmentFilter;
+
+    #[test]
+    fn empty_fields_set_no_condition() {
+        let filter = SegmentFilter::parse("", "2026-01-01", "", " blog ").unwrap();
+        assert_eq!(filter.tag_id, None);
+        assert_eq!(filter.signed_up_from, NaiveDate::from_ymd_opt(2026, 1, 1));
+        assert_eq!(filter.signed_up_until, None);
+        assert_eq!(filter.signup_source.as_deref(), Some("blog"));
+    }
+
+    #[test]
+    fn a_segment_without_conditions_is_rejected() {
+        assert_err!(SegmentFilter::parse(" ", "", "", ""));
+    }
+
+    #[test]
+    fn malformed_fields_are_rejected() {
+        assert_err!(SegmentFilter::parse("not-a-uuid", "", "", ""));
+        assert_err!(SegmentFilter::parse("", "01/02/2026", "", ""));
+        assert_err!(SegmentFilter::parse("", "2026-02-01", "2026-01-01", ""));
+    }
+}
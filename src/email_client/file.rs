@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{EmailMessage, EmailTransport, SendReceipt};
+
+/// Writes every message as an `.eml` file into `directory` instead of
+/// sending it. Meant for local development.
+pub struct FileTransport {
+    mailer: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileTransport {
+    pub fn new(directory:impl Into<PathBuf>) -> Self {
+        Self {
+            mailer: AsyncFileTransport::new(directory.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, message:&EmailMessage<'_>) -> Result<SendReceipt,anyhow::Error> {
+        let id = self.mailer.send(message.to_mime()?).await?;
+        tracing::debug!("Wrote email {}.eml", id);
+        Ok(SendReceipt { message_id: Some(id) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_ok;
+    use uuid::Uuid;
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, FileTransport};
+
+    #[tokio::test]
+    async fn file_transport_writes_an_eml_file() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let outcome = EmailClient::new(sender, FileTransport::new(&directory))
+            .send_email(
+                &recipient,
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text"
+            )
+            .await;
+        assert_ok!(outcome);
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("To: recipient@example.com"));
+        assert!(contents.contains("Subject: Newsletter title"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::message::{header::{HeaderName, HeaderValue}, Mailbox, MultiPart};
+use serde::Serialize;
+use crate::domain::SubscriberEmail;
+
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+#[derive(Clone)]
+pub struct EmailClient {
+    sender:SubscriberEmail,
+    transport: Arc<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(
+        sender:SubscriberEmail,
+        transport: impl EmailTransport + 'static,
+    ) -> Self {
+        Self {
+            sender,
+            transport: Arc::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        receipent: &SubscriberEmail,
+        subject: &str,
+        html_content:&str,
+        text_content:&str
+    ) -> Result<SendReceipt,anyhow::Error> {
+        self.send_email_with_headers(
+            receipent,
+            subject,
+            html_content,
+            text_content,
+            &[]
+        )
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        receipent: &SubscriberEmail,
+        subject: &str,
+        html_content:&str,
+        text_content:&str,
+        headers:&[EmailHeader<'_>]
+    ) -> Result<SendReceipt,anyhow::Error> {
+        let message = EmailMessage {
+            from: self.sender.as_ref(),
+            to: receipent.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+        self.transport.send(&message).await
+    }
+
+    /// Sends `emails` in batches of at most `MAX_BATCH_SIZE`, returning one
+    /// outcome per email, in order. When a whole batch is rejected every
+    /// email in it is reported as failed.
+    pub async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>]
+    ) -> Vec<Result<SendReceipt,SendFailure>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            let messages: Vec<_> = chunk
+                .iter()
+                .map(|email| EmailMessage {
+                    from: self.sender.as_ref(),
+                    to: email.recipient.as_ref(),
+                    subject: email.subject,
+                    html_body: email.html_content,
+                    text_body: email.text_content,
+                    headers: email.headers,
+                })
+                .collect();
+            match self.transport.send_batch(&messages).await {
+                Ok(results) => outcomes.extend(results),
+                Err(e) => outcomes.extend(chunk.iter().map(|_| Err(SendFailure::from(&e)))),
+            }
+        }
+        outcomes
+    }
+}
+
+/// The largest number of messages a single batch may hold.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// One email of a batch handed to `EmailClient::send_batch`.
+pub struct OutgoingEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+/// The way an `EmailClient` hands messages over for delivery.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, message:&EmailMessage<'_>) -> Result<SendReceipt,anyhow::Error>;
+
+    /// Sends several messages at once and returns one outcome per message.
+    /// Transports without a batch API send them one after the other.
+    async fn send_batch(
+        &self,
+        messages:&[EmailMessage<'_>]
+    ) -> Result<Vec<Result<SendReceipt,SendFailure>>,anyhow::Error> {
+        let mut outcomes = Vec::with_capacity(messages.len());
+        for message in messages {
+            outcomes.push(self.send(message).await.map_err(|e| SendFailure::from(&e)));
+        }
+        Ok(outcomes)
+    }
+}
+
+/// What the provider handed back for an accepted message.
+#[derive(Debug)]
+pub struct SendReceipt {
+    /// The provider's identifier for the message, used to match later
+    /// bounce or open events to this send.
+    pub message_id: Option<String>,
+}
+
+/// Why the provider did not accept a message.
+#[derive(Debug)]
+pub struct SendFailure {
+    /// The provider's error code, when it reported one.
+    pub error_code: Option<i32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for SendFailure {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.error_code {
+            Some(code) => write!(f, "{} (error code {})", self.message, code),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<&anyhow::Error> for SendFailure {
+    fn from(e:&anyhow::Error) -> Self {
+        Self {
+            error_code: None,
+            message: format!("{:#}", e),
+        }
+    }
+}
+
+/// A single outgoing message, as passed to an `EmailTransport`.
+pub struct EmailMessage<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+impl EmailMessage<'_> {
+    /// Builds the MIME message (plain text and HTML alternatives) used by
+    /// the SMTP and file transports.
+    fn to_mime(&self) -> Result<lettre::Message,anyhow::Error> {
+        let mut builder = lettre::Message::builder()
+            .from(self.from.parse::<Mailbox>().context("Invalid sender address")?)
+            .to(self.to.parse::<Mailbox>().context("Invalid recipient address")?)
+            .subject(self.subject)
+            .message_id(None);
+        for header in self.headers {
+            let name = HeaderName::new_from_ascii(header.name.to_owned())
+                .with_context(|| format!("Invalid header name {}", header.name))?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.to_owned()));
+        }
+        let message = builder.multipart(MultiPart::alternative_plain_html(
+            self.text_body.to_owned(),
+            self.html_body.to_owned()
+        ))?;
+        Ok(message)
+    }
+}
+
+/// A custom header attached to a single outgoing message.
+#[derive(Serialize)]
+#[serde(rename_all= "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use claim::{assert_err, assert_ok};
+    use fake::faker::lorem::ja_jp::Sentence;
+    use fake::faker::lorem::pt_br::Paragraph;
+    use secrecy::{SecretBox, SecretString};
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::ResponseTemplate;
+    use wiremock::{Mock, MockServer};
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, EmailHeader, OutgoingEmail, PostmarkTransport};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::{Fake, Faker};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value,_> = 
+                serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some() 
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+
+        let _ = email_client(mock_server.uri())
+            .send_email(&email(),
+            &subject(),
+            &content(),
+            &content()
+            )
+            .await;
+    }
+
+
+    struct HeadersBodyMatcher;
+
+    impl wiremock::Match for HeadersBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value,_> = 
+                serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body["Headers"][0]["Name"] == "List-Unsubscribe"
+                    && body["Headers"][0]["Value"] == "<https://example.com/unsubscribe>"
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_the_headers_in_the_body() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(HeadersBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://example.com/unsubscribe>",
+        }];
+        let outcome = email_client(mock_server.uri())
+            .send_email_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &headers
+            )
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+
+        let response = ResponseTemplate::new(200).set_delay(Duration::from_secs(300));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content()
+            )
+            .await;
+
+        assert_err!(outcome);
+
+    }
+    #[tokio::test]
+    async fn send_batch_reports_the_outcome_of_every_message() {
+        let mock_server = MockServer::start().await;
+
+        let results = serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"},
+            {"ErrorCode": 406, "Message": "You tried to send to an inactive recipient."},
+        ]);
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(results))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let outgoing = |recipient| OutgoingEmail {
+            recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        };
+        let outcomes = email_client(mock_server.uri())
+            .send_batch(&[outgoing(&first), outgoing(&second)])
+            .await;
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[1]["To"], second.as_ref());
+        assert_eq!(outcomes[0].as_ref().unwrap().message_id.as_deref(), Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817"));
+        assert_eq!(outcomes[1].as_ref().unwrap_err().error_code, Some(406));
+    }
+
+    #[tokio::test]
+    async fn send_batch_counts_unreported_messages_as_sent() {
+        let mock_server = MockServer::start().await;
+
+        let results = serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"},
+        ]);
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(results))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let outgoing = |recipient| OutgoingEmail {
+            recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        };
+        let client = email_client(mock_server.uri());
+
+        let outcomes = client.send_batch(&[outgoing(&first), outgoing(&second)]).await;
+        assert_eq!(outcomes[0].as_ref().unwrap().message_id.as_deref(), Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817"));
+        assert_eq!(outcomes[1].as_ref().unwrap().message_id, None);
+
+        let outcomes = client.send_batch(&[outgoing(&first), outgoing(&second)]).await;
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|outcome| outcome.as_ref().is_ok_and(|r| r.message_id.is_none())));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_message_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let outgoing = |recipient| OutgoingEmail {
+            recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            headers: &[],
+        };
+        let outcomes = email_client(mock_server.uri())
+            .send_batch(&[outgoing(&first), outgoing(&second)])
+            .await;
+
+        assert_eq!(outcomes.len(), 2);
+        assert_err!(&outcomes[0]);
+        assert_err!(&outcomes[1]);
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(mock_server_uri:String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(
+                mock_server_uri,
+                SecretString::new(Faker.fake::<String>().into_boxed_str()),
+                Duration::from_millis(200)))
+    }
+
+
+}
+
+
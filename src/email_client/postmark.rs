@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+
+use super::{EmailHeader, EmailMessage, EmailTransport, SendFailure, SendReceipt};
+
+/// Sends emails through Postmark's HTTP API.
+pub struct PostmarkTransport {
+    http_client:Client,
+    base_url:String,
+    authorization_token: SecretString,
+}
+
+impl PostmarkTransport {
+    pub fn new(base_url:String,
+        authorization_token:SecretString,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, message:&EmailMessage<'_>) -> Result<SendReceipt,anyhow::Error> {
+        let url = format!("{}/email",self.base_url);
+        let request_body = SendEmailRequest::from(message);
+
+        let response = self
+            .http_client
+            .post(&url)
+            .header("X-Postmark-Server-Token",
+                self.authorization_token.expose_secret()
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        // The message has been accepted at this point: an unreadable body
+        // must not turn it into a failure that would be sent again.
+        let message_id = response
+            .json::<SendResult>()
+            .await
+            .ok()
+            .and_then(|r| r.message_id);
+        Ok(SendReceipt { message_id })
+    }
+
+    async fn send_batch(
+        &self,
+        messages:&[EmailMessage<'_>]
+    ) -> Result<Vec<Result<SendReceipt,SendFailure>>,anyhow::Error> {
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let url = format!("{}/email/batch",self.base_url);
+        let request_body: Vec<_> = messages
+            .iter()
+            .map(SendEmailRequest::from)
+            .collect();
+
+        let response = self
+            .http_client
+            .post(&url)
+            .header("X-Postmark-Server-Token",
+                self.authorization_token.expose_secret()
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        // The batch has been accepted at this point, as in `send`: a message
+        // Postmark did not report on counts as sent, with no known id, rather
+        // than as a failure that would be sent again.
+        let results = match response.json::<Vec<SendResult>>().await {
+            Ok(results) => results,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to read the batch results"
+                );
+                Vec::new()
+            }
+        };
+        if results.len() != messages.len() {
+            tracing::warn!(
+                n_messages = messages.len(),
+                n_results = results.len(),
+                "Postmark did not report on every message of the batch"
+            );
+        }
+
+        let mut outcomes: Vec<_> = results
+            .into_iter()
+            .take(messages.len())
+            .map(|r| match r.error_code {
+                0 => Ok(SendReceipt { message_id: r.message_id }),
+                code => Err(SendFailure {
+                    error_code: Some(code),
+                    message: r.message,
+                }),
+            })
+            .collect();
+        outcomes.resize_with(messages.len(), || Ok(SendReceipt { message_id: None }));
+        Ok(outcomes)
+    }
+}
+
+impl<'a> From<&'a EmailMessage<'a>> for SendEmailRequest<'a> {
+    fn from(message:&'a EmailMessage<'a>) -> Self {
+        Self {
+            from: message.from,
+            to: message.to,
+            subject: message.subject,
+            html_body: message.html_body,
+            text_body: message.text_body,
+            headers: message.headers,
+        }
+    }
+}
+
+/// Postmark's response to `/email`, and each entry of the `/email/batch` response.
+#[derive(Deserialize)]
+#[serde(rename_all= "PascalCase")]
+struct SendResult {
+    error_code: i32,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all= "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject:&'a str,
+    html_body: &'a str,
+    text_body:&'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lettre::{transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{EmailMessage, EmailTransport, SendReceipt};
+
+/// Sends emails to any SMTP relay, authenticating with AUTH and
+/// upgrading the connection with STARTTLS when `starttls` is set.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host:&str,
+        port:u16,
+        username:String,
+        password:SecretString,
+        starttls:bool,
+        timeout:Duration,
+    ) -> Result<Self,anyhow::Error> {
+        let builder = if starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        let mailer = builder
+            .port(port)
+            .credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned()
+            ))
+            .timeout(Some(timeout))
+            .build();
+        Ok(Self { mailer })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, message:&EmailMessage<'_>) -> Result<SendReceipt,anyhow::Error> {
+        let message = message.to_mime()?;
+        let message_id = message.headers().get_raw("Message-ID").map(str::to_owned);
+        self.mailer.send(message).await?;
+        Ok(SendReceipt { message_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claim::assert_ok;
+    use secrecy::SecretString;
+    use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::TcpListener};
+
+    use crate::email_client::{EmailClient, EmailHeader, SmtpTransport};
+    use crate::domain::SubscriberEmail;
+
+    /// A bare-bones SMTP server that accepts a single message and
+    /// returns the commands it received along with the message data.
+    async fn smtp_stand_in(listener:TcpListener) -> (Vec<String>,String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut commands = Vec::new();
+        let mut data = String::new();
+
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let command = line.to_uppercase();
+            commands.push(line.clone());
+            let reply: &[u8] = if command.starts_with("EHLO") {
+                b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+            } else if command.starts_with("AUTH") {
+                b"235 2.7.0 Authentication successful\r\n"
+            } else if command.starts_with("DATA") {
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await.unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                b"250 2.0.0 Ok: queued\r\n"
+            } else if command.starts_with("QUIT") {
+                writer.write_all(b"221 2.0.0 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 2.0.0 Ok\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        (commands,data)
+    }
+
+    #[tokio::test]
+    async fn smtp_transport_authenticates_and_sends_the_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(smtp_stand_in(listener));
+
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            "user".into(),
+            SecretString::from("password"),
+            false,
+            Duration::from_secs(2)
+        ).unwrap();
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://example.com/unsubscribe>",
+        }];
+
+        let outcome = EmailClient::new(sender, transport)
+            .send_email_with_headers(
+                &recipient,
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                &headers
+            )
+            .await;
+        let receipt = assert_ok!(outcome);
+
+        let (commands, data) = server.await.unwrap();
+        let message_id = receipt.message_id.expect("No Message-ID was generated");
+        assert!(data.contains(&format!("Message-ID: {}", message_id)));
+        assert!(commands.iter().any(|c| c.starts_with("AUTH")));
+        assert!(commands.contains(&"MAIL FROM:<sender@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<recipient@example.com>".to_string()));
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(data.contains("Newsletter body as plain text"));
+    }
+}
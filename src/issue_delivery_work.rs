@@ -1,10 +1,13 @@
-use std::{clone, time::Duration};
+use std::{clone, collections::{hash_map::Entry, HashMap}, time::Duration};
 
-use sqlx::{PgConnection, PgPool, Postgres, Transaction};
-use tracing::{field::{self, display}, Span, Subscriber};
+use sqlx::{postgres::{PgListener, PgPoolOptions}, PgConnection, PgPool, Postgres, Transaction};
+use tokio::task::JoinSet;
+use tracing::{field, Span, Subscriber};
 use uuid::Uuid;
 
-use crate::{configuration::{self, Setting}, domain::SubscriberEmail, email_client::EmailClient, startup::get_connection_pool};
+use crate::{configuration::{self, IssueDeliverySettings, Setting}, domain::{render_merge_tags, EmailLayout, MergeFields, SubscriberEmail}, email_client::{EmailClient, EmailHeader, OutgoingEmail, MAX_BATCH_SIZE}, issue_scheduler::scheduler_loop, routes::unsubscribe_link, startup::HmacSecret};
+
+pub const NEW_TASKS_CHANNEL: &str = "issues_delivery_queue";
 
 pub enum ExecutionOutcome {
     TaskCompleted,
@@ -12,94 +15,330 @@ pub enum ExecutionOutcome {
 }
 
 
+/// A queued delivery of one issue to one subscriber.
+pub struct DeliveryTask {
+    pub newsletter_issues_id: Uuid,
+    pub subscriber_email: String,
+    pub subscriber_name: Option<String>,
+    pub n_retries: u32,
+}
+
 #[tracing::instrument(
     skip_all,
     fields(
-        newsletter_issue_id = field::Empty,
-        subscriber_email = field::Empty,
+        n_tasks = field::Empty,
     ),
     err
 )]
 pub async fn try_execute_task(
     pool:&PgPool,
-    email_client:&EmailClient
+    email_client:&EmailClient,
+    base_url:&str,
+    hmac_secret:&HmacSecret,
+    delivery_settings:&IssueDeliverySettings
 ) -> Result<ExecutionOutcome,sqlx::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    let dropped_issue_ids = drop_unreachable_tasks(pool).await?;
+    if !dropped_issue_ids.is_empty() {
+        complete_drained_issues(&mut *pool.acquire().await?, &dropped_issue_ids).await?;
+    }
+    let (mut transaction, tasks) = dequeue_tasks(
+        pool,
+        delivery_settings.batch_size.clamp(1, MAX_BATCH_SIZE)
+    ).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue)
-    }; 
-    if let Some((transaction,issue_id,email)) = dequeue_task(pool).await? {
-        Span::current()
-            .record("newsletter_issue_id", &display(&issue_id))
-            .record("subscriber_email", &display(&email));
-        match SubscriberEmail::parse(email.clone()) {
-            Ok(t) => {
-                let issue = select_from_newsletter_issues_id(pool, issue_id).await?;
-                if let Err(e) = email_client.send_email(
-                    &t, 
-                    &issue.title, 
-                    &issue.html_content, 
-                    &issue.text_content
-                )
-                    .await {
-                        tracing::error!(
-                            error.cause_chain = %e,
-                            error.message = %e,
-                            "Failed to deliver email to subscriber ! \
-                            Skipping.",
-                        )
+    };
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut issue_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_issues_id).collect();
+    issue_ids.sort();
+    issue_ids.dedup();
 
+    let mut issues = HashMap::new();
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => {
+                if let Entry::Vacant(entry) = issues.entry(task.newsletter_issues_id) {
+                    let issue = select_from_newsletter_issues_id(
+                        &mut transaction,
+                        task.newsletter_issues_id
+                    ).await?;
+                    entry.insert(issue);
                 }
+                deliverable.push((task, email));
             }
             Err(e) => {
                 tracing::error!(
                     error.cause_chain = %e,
                     error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issues_id,
+                    subscriber_email = %task.subscriber_email,
                     "Skipping a confirmed subscriber. \
                     their stored contact are invalid"
-                )
+                );
+                record_delivery(&mut transaction, &task, DeliveryStatus::Failed, None, None).await?;
+                fail_task(&mut transaction, &task, &e, task.n_retries).await?;
             }
         }
-        delete_task(transaction,issue_id,email).await?;
-    
     }
+
+    // Every recipient gets their own unsubscribe link, so the bodies and
+    // headers are built up front and borrowed by the batch.
+    let contents: Vec<_> = deliverable
+        .iter()
+        .map(|(task, email)| {
+            let unsubscribe_url = unsubscribe_link(base_url, email.as_ref(), hmac_secret);
+            let (html_content, text_content) = issues[&task.newsletter_issues_id]
+                .render(email.as_ref(), task.subscriber_name.as_deref(), &unsubscribe_url);
+            (format!("<{}>", unsubscribe_url), html_content, text_content)
+        })
+        .collect();
+    let headers: Vec<_> = contents
+        .iter()
+        .map(|(list_unsubscribe, _, _)| unsubscribe_headers(list_unsubscribe))
+        .collect();
+    let emails: Vec<_> = deliverable
+        .iter()
+        .zip(&contents)
+        .zip(&headers)
+        .map(|(((task, email), (_, html_content, text_content)), headers)| OutgoingEmail {
+            recipient: email,
+            subject: &issues[&task.newsletter_issues_id].title,
+            html_content,
+            text_content,
+            headers,
+        })
+        .collect();
+    let outcomes = email_client.send_batch(&emails).await;
+
+    for ((task, _), outcome) in deliverable.iter().zip(outcomes) {
+        let e = match outcome {
+            Ok(receipt) => {
+                record_delivery(
+                    &mut transaction,
+                    task,
+                    DeliveryStatus::Sent,
+                    receipt.message_id.as_deref(),
+                    None
+                ).await?;
+                delete_task(&mut transaction, task).await?;
+                continue;
+            }
+            Err(e) => e,
+        };
+        if task.n_retries < delivery_settings.max_retries {
+            let delay = delivery_settings.backoff(task.n_retries);
+            tracing::warn!(
+                error.message = %e,
+                newsletter_issue_id = %task.newsletter_issues_id,
+                subscriber_email = %task.subscriber_email,
+                n_retries = task.n_retries,
+                "Failed to deliver email to subscriber ! \
+                Retrying in {:?}.",
+                delay
+            );
+            record_delivery(&mut transaction, task, DeliveryStatus::Retrying, None, e.error_code).await?;
+            retry_task(&mut transaction, task, delay).await?;
+        } else {
+            tracing::error!(
+                error.message = %e,
+                newsletter_issue_id = %task.newsletter_issues_id,
+                subscriber_email = %task.subscriber_email,
+                n_retries = task.n_retries,
+                "Failed to deliver email to subscriber ! \
+                Giving up.",
+            );
+            record_delivery(&mut transaction, task, DeliveryStatus::Failed, None, e.error_code).await?;
+            fail_task(&mut transaction, task, &e.to_string(), task.n_retries + 1).await?;
+        }
+    }
+    transaction.commit().await?;
+
+    // Only check once committed, so that the last worker to finish an
+    // issue sees every other worker's deletions.
+    complete_drained_issues(&mut *pool.acquire().await?, &issue_ids).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
-pub async fn dequeue_task(
+/// Queues a delivery for every subscriber in the audience of the issue and
+/// returns how many were queued. An issue sent to lists only goes to their
+/// confirmed members, once each however many of the lists they are on, and
+/// an issue sent to a segment only to the subscribers its filter matches.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_newsletter_issue(
+    transaction: &mut PgConnection,
+    newsletter_issue_id:Uuid
+) -> Result<u64,sqlx::Error> {
+    let n_enqueued = sqlx::query!(
+        r#"
+        INSERT INTO issues_delivery_queue (
+            newsletter_issues_id,
+            subscriber_email
+        )
+        SELECT $1, a.email
+        FROM issue_audience(
+            (SELECT segment_id FROM newsletter_issues WHERE newsletter_issues_id = $1),
+            ARRAY(SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issues_id = $1)
+        ) a
+        "#,
+        newsletter_issue_id
+    )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+    // With no recipients there is nothing for a worker to pick up.
+    complete_drained_issues(&mut *transaction, &[newsletter_issue_id]).await?;
+    notify_new_tasks(transaction).await?;
+    Ok(n_enqueued)
+}
+
+/// How many deliveries [`enqueue_newsletter_issue`] would queue right now
+/// for an issue sent to `list_ids` and `segment_id`.
+#[tracing::instrument(skip(connection))]
+pub async fn count_recipients(
+    connection:&mut PgConnection,
+    segment_id:Option<Uuid>,
+    list_ids:&[Uuid]
+) -> Result<i64,sqlx::Error> {
+    let count = sqlx::query!(
+        r#"SELECT count(*) AS "count!" FROM issue_audience($1, $2)"#,
+        segment_id,
+        list_ids
+    )
+        .fetch_one(connection)
+        .await?
+        .count;
+    Ok(count)
+}
+
+/// Moves the issues among `issue_ids` that are still `sending` but have
+/// nothing left in the queue to `completed`.
+#[tracing::instrument(skip_all)]
+pub async fn complete_drained_issues(
+    connection:&mut PgConnection,
+    issue_ids:&[Uuid]
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET delivery_state = 'completed'
+        WHERE i.newsletter_issues_id = ANY($1)
+        AND i.delivery_state = 'sending'
+        AND NOT EXISTS (
+            SELECT 1 FROM issues_delivery_queue q
+            WHERE q.newsletter_issues_id = i.newsletter_issues_id
+        )
+        "#,
+        issue_ids
+    )
+        .execute(connection)
+        .await?;
+    Ok(())
+}
+
+/// Deletes the queued deliveries to subscribers who unsubscribed, or whose
+/// address got suppressed, since their issue was enqueued. Returns the issues
+/// they belonged to, which may now have nothing left to send.
+#[tracing::instrument(skip_all)]
+pub async fn drop_unreachable_tasks(
     pool:&PgPool
-) -> Result<Option<(PgTransaction,Uuid,String)>,sqlx::Error> {
-    let transaction = pool.begin().await?;
-    let r = sqlx::query!(
+) -> Result<Vec<Uuid>,sqlx::Error> {
+    let dropped = sqlx::query!(
         r#"
-        SELECT newsletter_issues_id, subscriber_email
+        DELETE FROM issues_delivery_queue
+        WHERE (newsletter_issues_id, subscriber_email) IN (
+            SELECT q.newsletter_issues_id, q.subscriber_email
+            FROM issues_delivery_queue q
+            WHERE q.newsletter_issues_id IN (
+                SELECT newsletter_issues_id
+                FROM newsletter_issues
+                WHERE delivery_state = 'sending'
+            )
+            AND (
+                q.subscriber_email IN (SELECT email FROM email_suppressions)
+                OR EXISTS (
+                    SELECT 1 FROM subscriptions s
+                    WHERE s.email = q.subscriber_email
+                    AND s.status <> 'confirmed'
+                )
+            )
+            FOR UPDATE
+            SKIP LOCKED
+        )
+        RETURNING newsletter_issues_id, subscriber_email
+        "#
+    )
+        .fetch_all(pool)
+        .await?;
+    let mut issue_ids = Vec::with_capacity(dropped.len());
+    for task in dropped {
+        tracing::info!(
+            newsletter_issue_id = %task.newsletter_issues_id,
+            subscriber_email = %task.subscriber_email,
+            "Dropping a delivery to a subscriber who can no longer be emailed."
+        );
+        issue_ids.push(task.newsletter_issues_id);
+    }
+    issue_ids.sort();
+    issue_ids.dedup();
+    Ok(issue_ids)
+}
+
+/// Locks up to `batch_size` due tasks. They stay locked, and invisible to
+/// other workers, until the returned transaction ends. Tasks to subscribers
+/// who can no longer be emailed are left for [`drop_unreachable_tasks`].
+pub async fn dequeue_tasks(
+    pool:&PgPool,
+    batch_size:usize
+) -> Result<(PgTransaction,Vec<DeliveryTask>),sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let tasks = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issues_id,
+            subscriber_email,
+            (SELECT name FROM subscriptions WHERE email = subscriber_email) AS subscriber_name,
+            n_retries
         FROM issues_delivery_queue
+        WHERE execute_after <= now()
+        AND newsletter_issues_id IN (
+            SELECT newsletter_issues_id
+            FROM newsletter_issues
+            WHERE delivery_state = 'sending'
+        )
+        AND subscriber_email NOT IN (SELECT email FROM email_suppressions)
+        AND NOT EXISTS (
+            SELECT 1 FROM subscriptions
+            WHERE email = subscriber_email
+            AND status <> 'confirmed'
+        )
         FOR UPDATE
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
         "#,
+        batch_size as i64
     )
-        .fetch_optional(pool)
-        .await?;
-
-    if let Some(r) = r {
-        Ok(Some((
-                    transaction,
-                    r.newsletter_issues_id,
-                    r.subscriber_email
-        )))
-    } else {
-        Ok(None)
-    }
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|r| DeliveryTask {
+            newsletter_issues_id: r.newsletter_issues_id,
+            subscriber_email: r.subscriber_email,
+            subscriber_name: r.subscriber_name,
+            n_retries: r.n_retries as u32,
+        })
+        .collect();
+    Ok((transaction, tasks))
 }
 
 #[tracing::instrument(
     skip_all,
 )]
 pub async fn delete_task(
-    mut transaction:PgTransaction,
-    newsletter_issues_id:Uuid,
-    subscriber_email:String
+    transaction:&mut PgConnection,
+    task:&DeliveryTask
 ) -> Result<(),sqlx::Error> {
 
     let _r = sqlx::query!(
@@ -109,47 +348,245 @@ pub async fn delete_task(
         newsletter_issues_id = $1 AND
         subscriber_email = $2
         "#,
-        newsletter_issues_id,
-        subscriber_email
+        task.newsletter_issues_id,
+        task.subscriber_email
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+)]
+pub async fn retry_task(
+    transaction:&mut PgConnection,
+    task:&DeliveryTask,
+    delay:Duration
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issues_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+        newsletter_issues_id = $1 AND
+        subscriber_email = $2
+        "#,
+        task.newsletter_issues_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Moves a task that will not be retried anymore into `issues_delivery_failures`.
+#[tracing::instrument(
+    skip_all,
+)]
+pub async fn fail_task(
+    transaction:&mut PgConnection,
+    task:&DeliveryTask,
+    last_error:&str,
+    n_attempts:u32
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issues_delivery_failures (
+            newsletter_issues_id,
+            subscriber_email,
+            last_error,
+            n_attempts,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issues_id, subscriber_email) DO UPDATE
+        SET
+            last_error = EXCLUDED.last_error,
+            n_attempts = EXCLUDED.n_attempts,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issues_id,
+        task.subscriber_email,
+        last_error,
+        n_attempts as i32
     )
         .execute(&mut *transaction)
         .await?;
+    delete_task(transaction, task).await
+}
+
+/// The latest known state of a delivery, as stored in `deliveries.status`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum DeliveryStatus {
+    Sent,
+    Retrying,
+    Failed,
+    Bounced,
+    SoftBounced,
+    Complained,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Retrying => "retrying",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+            DeliveryStatus::SoftBounced => "soft_bounced",
+            DeliveryStatus::Complained => "complained",
+        }
+    }
+}
+
+/// Records the outcome of the latest attempt to deliver `task` in `deliveries`.
+#[tracing::instrument(
+    skip_all,
+)]
+pub async fn record_delivery(
+    transaction:&mut PgConnection,
+    task:&DeliveryTask,
+    status:DeliveryStatus,
+    provider_message_id:Option<&str>,
+    error_code:Option<i32>
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO deliveries (
+            newsletter_issues_id,
+            subscriber_email,
+            provider_message_id,
+            status,
+            error_code,
+            sent_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (newsletter_issues_id, subscriber_email) DO UPDATE
+        SET
+            provider_message_id = EXCLUDED.provider_message_id,
+            status = EXCLUDED.status,
+            error_code = EXCLUDED.error_code,
+            sent_at = EXCLUDED.sent_at
+        "#,
+        task.newsletter_issues_id,
+        task.subscriber_email,
+        provider_message_id,
+        status.as_str(),
+        error_code
+    )
+        .execute(transaction)
+        .await?;
     Ok(())
 }
 
+/// The headers letting mail clients offer a one-click unsubscribe, given the
+/// `<url>` of the subscriber's unsubscribe link.
+pub fn unsubscribe_headers(list_unsubscribe:&str) -> [EmailHeader<'_>;2] {
+    [
+        EmailHeader {
+            name: "List-Unsubscribe",
+            value: list_unsubscribe,
+        },
+        EmailHeader {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click",
+        },
+    ]
+}
+
 pub struct NewsletterIssue {
-    title:String,
-    text_content:String,
-    html_content:String,
+    pub title:String,
+    pub text_content:String,
+    pub html_content:String,
+    pub layout:Option<EmailLayout>,
+}
+
+impl NewsletterIssue {
+    /// Appends the subscriber's unsubscribe link to both bodies of the issue,
+    /// wraps them in the issue's layout, if it has one, then fills in the
+    /// merge tags for this subscriber.
+    pub fn render(
+        &self,
+        subscriber_email:&str,
+        subscriber_name:Option<&str>,
+        unsubscribe_url:&str
+    ) -> (String,String) {
+        let html_content = format!(
+            "{}<p><a href=\"{}\">Unsubscribe</a> from this newsletter.</p>",
+            self.html_content,
+            unsubscribe_url
+        );
+        let text_content = format!(
+            "{}\n\nUnsubscribe from this newsletter: {}",
+            self.text_content,
+            unsubscribe_url
+        );
+        let (html_content, text_content) = match &self.layout {
+            Some(layout) => layout.wrap(&html_content, &text_content),
+            None => (html_content,text_content),
+        };
+        let fields = MergeFields {
+            subscriber_name,
+            subscriber_email,
+            unsubscribe_url,
+            issue_title: &self.title,
+        };
+        (
+            render_merge_tags(&html_content, &fields, true),
+            render_merge_tags(&text_content, &fields, false),
+        )
+    }
 }
 
 #[tracing::instrument(skip_all)]
 pub async fn select_from_newsletter_issues_id(
-    pool:&PgPool,
+    transaction:&mut PgConnection,
     newsletter_issues_id:Uuid
 ) -> Result<NewsletterIssue,sqlx::Error> {
-    let query = sqlx::query_as!(
-        NewsletterIssue,
+    let row = sqlx::query!(
         r#"
-        SELECT title, text_content, html_content
-        FROM newsletter_issues
-        WHERE newsletter_issues_id = $1
+        SELECT
+            i.title,
+            i.text_content,
+            i.html_content,
+            t.html_layout AS "html_layout?",
+            t.text_layout AS "text_layout?"
+        FROM newsletter_issues i
+        LEFT JOIN email_templates t USING (email_template_id)
+        WHERE i.newsletter_issues_id = $1
         "#,
         newsletter_issues_id
-    ).fetch_one(pool)
+    ).fetch_one(transaction)
         .await?;
-    Ok(query)
+    let layout = match (row.html_layout, row.text_layout) {
+        (Some(html_layout), Some(text_layout)) => Some(EmailLayout {html_layout, text_layout}),
+        _ => None,
+    };
+    Ok(NewsletterIssue {
+        title: row.title,
+        text_content: row.text_content,
+        html_content: row.html_content,
+        layout,
+    })
 }
 
 #[tracing::instrument(skip_all)]
 pub async fn workers_loop(
     pool:&PgPool,
-    email_client:&EmailClient
+    email_client:&EmailClient,
+    base_url:&str,
+    hmac_secret:&HmacSecret,
+    delivery_settings:&IssueDeliverySettings
 ) -> Result<(),anyhow::Error> {
+    let mut listener = None;
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, base_url, hmac_secret, delivery_settings).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                wait_for_new_tasks(pool, &mut listener, delivery_settings.poll_interval()).await;
             },
             Err(_) => {
                 tokio::time::sleep(Duration::from_secs(1)).await;
@@ -160,10 +597,74 @@ pub async fn workers_loop(
 
 }
 
+/// Blocks until a NOTIFY arrives on `NEW_TASKS_CHANNEL` or `poll_interval` elapses.
+/// If the listener connection cannot be established we simply poll.
+async fn wait_for_new_tasks(
+    pool:&PgPool,
+    listener:&mut Option<PgListener>,
+    poll_interval:Duration
+) {
+    if listener.is_none() {
+        match listen_for_new_tasks(pool).await {
+            Ok(l) => *listener = Some(l),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to LISTEN for new tasks. Falling back to polling."
+                )
+            }
+        }
+    }
+
+    match listener {
+        Some(l) => {
+            // A notification, a timeout, or a dropped connection that has
+            // already been re-established all mean: check the queue again.
+            if let Ok(Err(e)) = tokio::time::timeout(poll_interval, l.try_recv()).await {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Lost the LISTEN connection. Falling back to polling."
+                );
+                *listener = None;
+                tokio::time::sleep(poll_interval).await;
+            }
+        }
+        None => tokio::time::sleep(poll_interval).await,
+    }
+}
+
+async fn listen_for_new_tasks(pool:&PgPool) -> Result<PgListener,sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(NEW_TASKS_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Wakes up idle workers. Notifications are only delivered once `transaction` commits.
+#[tracing::instrument(skip_all)]
+pub async fn notify_new_tasks(
+    transaction:&mut PgConnection
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        "SELECT pg_notify($1, '')",
+        NEW_TASKS_CHANNEL
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
 pub async fn run_worker_until_stopped(
     setting:Setting
 ) -> Result<(),anyhow::Error> {
-    let connection_pool = get_connection_pool(&setting.database);
+    let n_workers = setting.issue_delivery.workers.max(1);
+    // Every worker holds one connection for its current task and one for LISTEN,
+    // plus one for the scheduler.
+    let connection_pool = PgPoolOptions::new()
+        .max_connections(2 * n_workers as u32 + 2)
+        .idle_timeout(Duration::from_secs(2))
+        .connect_lazy_with(setting.database.connection_string());
     let sender = setting.email_client.sender().expect("Failed to get email sender");
     let timeout = setting.email_client.timeout();
     // let email_client = EmailClient::new(
@@ -172,8 +673,33 @@ pub async fn run_worker_until_stopped(
     //     setting.email_client.authorization_token,
     //     timeout);
     let email_client = setting.email_client.client();
-    workers_loop(&connection_pool, &email_client).await
 
+    let mut workers = JoinSet::new();
+    for _ in 0..n_workers {
+        let connection_pool = connection_pool.clone();
+        let email_client = email_client.clone();
+        let setting = setting.clone();
+        workers.spawn(async move {
+            workers_loop(
+                &connection_pool,
+                &email_client,
+                &setting.application.base_url,
+                &setting.application.hmac_secret,
+                &setting.issue_delivery
+            ).await
+        });
+    }
+    tracing::info!("Started {} delivery workers", n_workers);
+
+    let poll_interval = setting.issue_delivery.poll_interval();
+    workers.spawn(async move {
+        scheduler_loop(&connection_pool, poll_interval).await
+    });
+
+    match workers.join_next().await {
+        Some(outcome) => outcome?,
+        None => Ok(()),
+    }
 } 
 
 type PgTransaction = Transaction<'static,Postgres>;
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::issue_delivery_work::enqueue_newsletter_issue;
+
+
+/// Publishes at most one scheduled issue whose time has come and returns
+/// its id, or `None` if no issue is due.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = tracing::field::Empty,
+        n_enqueued = tracing::field::Empty,
+    ),
+    err
+)]
+pub async fn try_publish_scheduled_issue(
+    pool:&PgPool
+) -> Result<Option<Uuid>,sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    // SKIP LOCKED lets several worker processes run their scheduler
+    // side by side without publishing an issue twice.
+    let due = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id
+        FROM newsletter_issues
+        WHERE delivery_state = 'scheduled'
+        AND scheduled_for <= now()
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    let Some(due) = due else {
+        return Ok(None);
+    };
+    let issue_id = due.newsletter_issues_id;
+    tracing::Span::current().record("newsletter_issue_id", tracing::field::display(&issue_id));
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'sending', published_at = now()
+        WHERE newsletter_issues_id = $1
+        "#,
+        issue_id
+    )
+        .execute(&mut *transaction)
+        .await?;
+    let n_enqueued = enqueue_newsletter_issue(&mut transaction, issue_id).await?;
+    tracing::Span::current().record("n_enqueued", n_enqueued);
+    transaction.commit().await?;
+    Ok(Some(issue_id))
+}
+
+/// Publishes due issues, then checks again every `poll_interval`.
+pub async fn scheduler_loop(
+    pool:&PgPool,
+    poll_interval:Duration
+) -> Result<(),anyhow::Error> {
+    loop {
+        match try_publish_scheduled_issue(pool).await {
+            Ok(Some(_)) => {}
+            Ok(None) => tokio::time::sleep(poll_interval).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+}
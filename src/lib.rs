@@ -18,12 +18,20 @@ pub mod session_crate;
 pub mod middleware;
 pub mod idempotency;
 pub mod issue_delivery_work;
+pub mod issue_scheduler;
+pub mod markdown;
 
 
 #[derive(Deserialize)]
 pub struct FormData {
     email:String,
     name:String,
+    /// The slug of the list to join. Left empty, the subscriber joins no list.
+    #[serde(default)]
+    list:String,
+    /// Where the form is embedded, kept to build segments. May be left empty.
+    #[serde(default)]
+    source:String,
 }
 
 
@@ -0,0 +1,351 @@
+//! Renders the Markdown source of newsletter issues.
+//!
+//! Markdown is parsed with `pulldown-cmark`. The HTML part is sanitized with
+//! `ammonia`, and links are only kept for `http`, `https`, `mailto` and
+//! relative URLs. The text part is rendered from the same events.
+//!
+//! Merge tags are swapped for placeholders before parsing, so that Markdown
+//! never reads into them and they come out exactly as they were written.
+
+use std::collections::HashSet;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use uuid::Uuid;
+
+/// Renders `markdown` into sanitized HTML.
+pub fn to_html(markdown:&str) -> String {
+    let (markdown, tags) = MergeTags::protect(markdown);
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, drop_unsafe_links(Parser::new(&markdown)));
+    let html = ammonia::Builder::default()
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(None)
+        .clean(&html)
+        .to_string();
+    tags.restore(html.trim_end(), htmlescape::encode_minimal)
+}
+
+/// Renders `markdown` into a plain-text version meant to be read as is.
+pub fn to_text(markdown:&str) -> String {
+    let (markdown, tags) = MergeTags::protect(markdown);
+    let mut renderer = TextRenderer::default();
+    for event in Parser::new(&markdown) {
+        // Restored early so that headings are underlined to their real length.
+        renderer.push(match event {
+            Event::Text(text) => Event::Text(tags.restore(&text, str::to_string).into()),
+            event => event,
+        });
+    }
+    tags.restore(&renderer.finish(), str::to_string)
+}
+
+/// Fills in the HTML and text parts of an issue from its Markdown source,
+/// unless the admin wrote them by hand.
+pub fn render_issue_parts(markdown:&str, html_content:&str, text_content:&str) -> (String,String) {
+    let has_markdown = !markdown.trim().is_empty();
+    let html_content = if has_markdown && html_content.trim().is_empty() {
+        to_html(markdown)
+    } else {
+        html_content.to_string()
+    };
+    let text_content = if has_markdown && text_content.trim().is_empty() {
+        to_text(markdown)
+    } else {
+        text_content.to_string()
+    };
+    (html_content, text_content)
+}
+
+/// The merge tags taken out of a Markdown source, in order.
+struct MergeTags<'a> {
+    nonce: String,
+    tags: Vec<&'a str>,
+}
+
+impl<'a> MergeTags<'a> {
+    fn protect(markdown:&'a str) -> (String, Self) {
+        let mut merge_tags = MergeTags { nonce: Uuid::new_v4().simple().to_string(), tags: Vec::new() };
+        let mut protected = String::with_capacity(markdown.len());
+        let mut rest = markdown;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+                break;
+            };
+            protected.push_str(&rest[..start]);
+            protected.push_str(&merge_tags.placeholder(merge_tags.tags.len()));
+            merge_tags.tags.push(&rest[start..end]);
+            rest = &rest[end..];
+        }
+        protected.push_str(rest);
+        (protected, merge_tags)
+    }
+
+    /// Letters and digits only: Markdown leaves it alone, HTML needs no escaping
+    /// and it passes as a relative URL.
+    fn placeholder(&self, index:usize) -> String {
+        format!("m{}x{}x", self.nonce, index)
+    }
+
+    fn restore(&self, rendered:&str, encode:impl Fn(&str) -> String) -> String {
+        self.tags
+            .iter()
+            .enumerate()
+            .fold(rendered.to_string(), |rendered, (index, tag)| {
+                rendered.replace(&self.placeholder(index), &encode(tag))
+            })
+    }
+}
+
+fn safe_url(url:&str) -> bool {
+    let lowercase = url.to_ascii_lowercase();
+    ["http://", "https://", "mailto:"].iter().any(|scheme| lowercase.starts_with(scheme)) || !url.contains(':')
+}
+
+/// Unwraps links and images whose URL is not safe, keeping their text.
+fn drop_unsafe_links<'a>(events:impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    let mut kept = Vec::new();
+    events.filter(move |event| match event {
+        Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+            kept.push(safe_url(dest_url));
+            *kept.last().unwrap()
+        }
+        Event::End(TagEnd::Link | TagEnd::Image) => kept.pop().unwrap_or(true),
+        _ => true,
+    })
+}
+
+enum Frame {
+    Root,
+    Paragraph,
+    Heading(HeadingLevel),
+    Code,
+    Quote,
+    List(Option<u64>),
+    Item,
+    Link(String),
+}
+
+/// Renders the events of a Markdown document into plain text.
+///
+/// Each open block gets a frame collecting the text of its children; closing
+/// it renders the frame into a block of its parent.
+struct TextRenderer {
+    frames: Vec<(Frame, Vec<String>, String)>,
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        TextRenderer { frames: vec![(Frame::Root, Vec::new(), String::new())] }
+    }
+}
+
+impl TextRenderer {
+    fn push(&mut self, event:Event) {
+        match event {
+            Event::Start(tag) => {
+                let frame = match tag {
+                    Tag::Paragraph => Frame::Paragraph,
+                    Tag::Heading { level, .. } => Frame::Heading(level),
+                    Tag::CodeBlock(_) => Frame::Code,
+                    Tag::BlockQuote(_) => Frame::Quote,
+                    Tag::List(start) => Frame::List(start),
+                    Tag::Item => Frame::Item,
+                    Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => Frame::Link(dest_url.to_string()),
+                    _ => return,
+                };
+                self.frames.push((frame, Vec::new(), String::new()));
+            }
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::CodeBlock
+                | TagEnd::BlockQuote(_)
+                | TagEnd::List(_)
+                | TagEnd::Item
+                | TagEnd::Link
+                | TagEnd::Image,
+            ) => self.close(),
+            Event::Text(text) | Event::Code(text) => self.text().push_str(&text),
+            Event::SoftBreak | Event::HardBreak => self.text().push('\n'),
+            Event::Rule => self.push_block("-".repeat(20)),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let (_, mut blocks, text) = self.frames.pop().expect("the root frame is never closed");
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+        blocks.join("\n\n")
+    }
+
+    fn text(&mut self) -> &mut String {
+        &mut self.frames.last_mut().expect("the root frame is never closed").2
+    }
+
+    fn push_block(&mut self, block:String) {
+        let (_, blocks, text) = self.frames.last_mut().expect("the root frame is never closed");
+        if !text.is_empty() {
+            blocks.push(std::mem::take(text));
+        }
+        blocks.push(block);
+    }
+
+    fn close(&mut self) {
+        let Some((frame, mut blocks, text)) = self.frames.pop() else {
+            return;
+        };
+        if !text.is_empty() {
+            blocks.push(text);
+        }
+        match frame {
+            Frame::Root => unreachable!("the root frame is never closed"),
+            Frame::Link(url) => {
+                let text = blocks.concat();
+                let link = if text == url || format!("mailto:{}", text) == url || url.is_empty() {
+                    if text.is_empty() { url } else { text }
+                } else {
+                    format!("{} ({})", text, url)
+                };
+                self.text().push_str(&link);
+            }
+            Frame::Paragraph => self.push_block(blocks.concat().trim_end().to_string()),
+            Frame::Heading(level) => {
+                let heading = blocks.concat();
+                let underline = match level {
+                    HeadingLevel::H1 => "=".repeat(heading.chars().count()),
+                    HeadingLevel::H2 => "-".repeat(heading.chars().count()),
+                    _ => String::new(),
+                };
+                self.push_block(format!("{}\n{}", heading, underline).trim_end().to_string());
+            }
+            Frame::Code => {
+                let code = blocks.concat();
+                self.push_block(code.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n"));
+            }
+            Frame::Quote => {
+                let quote = blocks
+                    .join("\n\n")
+                    .lines()
+                    .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.push_block(quote);
+            }
+            Frame::Item => self.push_block(blocks.join("\n")),
+            Frame::List(start) => {
+                let list = blocks
+                    .iter()
+                    .enumerate()
+                    .map(|(n, item)| {
+                        let marker = match start {
+                            Some(start) => format!("{}.", start + n as u64),
+                            None => "-".to_string(),
+                        };
+                        let indent = " ".repeat(marker.len() + 1);
+                        format!("{} {}", marker, item.replace('\n', &format!("\n{}", indent)))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.push_block(list);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_issue_parts, to_html, to_text};
+
+    const ISSUE: &str = "# Hello *readers*
+
+Welcome to **issue 3** of `zero2prod`.
+Read [the book](https://zero2prod.com).
+
+- one
+- two_and_a_half
+
+> Quoted
+---";
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        assert_eq!(
+            to_html(ISSUE),
+            "<h1>Hello <em>readers</em></h1>
+<p>Welcome to <strong>issue 3</strong> of <code>zero2prod</code>.
+Read <a href=\"https://zero2prod.com\">the book</a>.</p>
+<ul>
+<li>one</li>
+<li>two_and_a_half</li>
+</ul>
+<blockquote>
+<p>Quoted</p>
+</blockquote>
+<hr>"
+        );
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_readable_text() {
+        assert_eq!(
+            to_text(ISSUE),
+            "Hello readers
+=============
+
+Welcome to issue 3 of zero2prod.
+Read the book (https://zero2prod.com).
+
+- one
+- two_and_a_half
+
+> Quoted
+
+--------------------"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_sanitized() {
+        assert_eq!(
+            to_html("Hi <script>alert(1)</script> & <b onclick=\"steal()\">bold</b>"),
+            "<p>Hi  &amp; <b>bold</b></p>"
+        );
+    }
+
+    #[test]
+    fn unsafe_links_are_dropped() {
+        assert_eq!(to_html("[click](javascript:alert(1))"), "<p>click</p>");
+        assert_eq!(to_html("[click](data:text/html,x)"), "<p>click</p>");
+    }
+
+    #[test]
+    fn code_blocks_are_kept_verbatim() {
+        assert_eq!(to_html("```\nlet x = *y*;\n```"), "<pre><code>let x = *y*;\n</code></pre>");
+        assert_eq!(to_text("```\nlet x = *y*;\n```"), "    let x = *y*;");
+    }
+
+    #[test]
+    fn ordered_lists_are_numbered() {
+        assert_eq!(to_text("3. first\n7. second"), "3. first\n4. second");
+        assert_eq!(to_html("1. first"), "<ol>\n<li>first</li>\n</ol>");
+    }
+
+    #[test]
+    fn merge_tags_are_left_intact() {
+        assert_eq!(
+            to_html("Hi {{ subscriber_name }}, _see_ [here]({{ unsubscribe_url }}) {{ unsubscribe_url }}"),
+            "<p>Hi {{ subscriber_name }}, <em>see</em> <a href=\"{{ unsubscribe_url }}\">here</a> {{ unsubscribe_url }}</p>"
+        );
+    }
+
+    #[test]
+    fn hand_written_parts_override_the_rendered_ones() {
+        let (html, text) = render_issue_parts("*hi*", "<p>custom</p>", "");
+        assert_eq!(html, "<p>custom</p>");
+        assert_eq!(text, "hi");
+
+        let (html, text) = render_issue_parts("", "", "");
+        assert_eq!((html.as_str(), text.as_str()), ("", ""));
+    }
+}
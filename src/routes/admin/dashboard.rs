@@ -55,6 +55,14 @@ pub async fn dashboard_page(
                 </form>
                 </li>
                 <li> <a href="/admin/newsletter"> Publish Newsletter </a></li>
+                <li> <a href="/admin/drafts"> Drafts </a></li>
+                <li> <a href="/admin/templates"> Email Templates </a></li>
+                <li> <a href="/admin/lists"> Lists </a></li>
+                <li> <a href="/admin/tags"> Tags </a></li>
+                <li> <a href="/admin/segments"> Segments </a></li>
+                <li> <a href="/admin/issues/scheduled"> Scheduled Issues </a></li>
+                <li> <a href="/admin/search"> Search Issues </a></li>
+                <li> <a href="/admin/deliveries/failed"> Failed Deliveries </a></li>
                 </ol>
                 </body>
                 </html>"#
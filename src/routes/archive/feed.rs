@@ -0,0 +1,244 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{http::header::{ETag, EntityTag, IfModifiedSince, IfNoneMatch, LastModified}, web, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{routes::{e500, public_content}, startup::ApplicationBaseUrl};
+
+
+pub const FEED_TITLE: &str = "Our newsletter";
+/// How many of the latest issues the feeds carry.
+pub const FEED_LENGTH: i64 = 20;
+
+struct FeedEntry {
+    newsletter_issues_id:Uuid,
+    slug:String,
+    title:String,
+    html_content:String,
+    published_at:i64,
+}
+
+/// What a feed's validators are derived from: when any published issue,
+/// hidden ones included, last changed, and how many are in the archive.
+/// Both are cheap to read, so a client polling with a validator it already
+/// holds gets its `304` before any issue is loaded.
+struct FeedVersion {
+    /// In seconds since the epoch, for `Last-Modified`.
+    last_modified:Option<i64>,
+    /// In microseconds since the epoch, so that two changes within the same
+    /// second still give distinct ETags.
+    changed_at:Option<i64>,
+    n_visible:i64,
+}
+
+#[tracing::instrument(
+    name = "Get the RSS feed",
+    skip(request,pool,base_url)
+)]
+pub async fn rss_feed(
+    request:HttpRequest,
+    pool:web::Data<PgPool>,
+    base_url:web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let version = get_feed_version(&pool).await.map_err(e500)?;
+    if version.is_known_to(&request) {
+        return Ok(version.validators(HttpResponse::NotModified()).finish());
+    }
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let mut items = String::new();
+    for entry in &entries {
+        writeln!(
+            items,
+            r#"<item>
+<title>{}</title>
+<link>{base_url}/archive/{}</link>
+<guid isPermaLink="false">urn:uuid:{}</guid>
+<pubDate>{}</pubDate>
+<description>{}</description>
+</item>"#,
+            xml_escape(&entry.title),
+            entry.slug,
+            entry.newsletter_issues_id,
+            timestamp(entry.published_at).to_rfc2822(),
+            xml_escape(&public_content(&entry.title, &entry.html_content)),
+        ).unwrap();
+    }
+    let last_build_date = version.last_modified
+        .map(|t| format!("<lastBuildDate>{}</lastBuildDate>\n", timestamp(t).to_rfc2822()))
+        .unwrap_or_default();
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+<channel>
+<title>{FEED_TITLE}</title>
+<link>{base_url}/archive</link>
+<description>Every issue of {FEED_TITLE}.</description>
+{last_build_date}{items}</channel>
+</rss>
+"#
+    );
+    Ok(version
+        .validators(HttpResponse::Ok())
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(body))
+}
+
+#[tracing::instrument(
+    name = "Get the Atom feed",
+    skip(request,pool,base_url)
+)]
+pub async fn atom_feed(
+    request:HttpRequest,
+    pool:web::Data<PgPool>,
+    base_url:web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let version = get_feed_version(&pool).await.map_err(e500)?;
+    if version.is_known_to(&request) {
+        return Ok(version.validators(HttpResponse::NotModified()).finish());
+    }
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let mut items = String::new();
+    for entry in &entries {
+        let published_at = atom_date(entry.published_at);
+        writeln!(
+            items,
+            r#"<entry>
+<title>{}</title>
+<link href="{base_url}/archive/{}"/>
+<id>urn:uuid:{}</id>
+<published>{published_at}</published>
+<updated>{published_at}</updated>
+<content type="html">{}</content>
+</entry>"#,
+            xml_escape(&entry.title),
+            entry.slug,
+            entry.newsletter_issues_id,
+            xml_escape(&public_content(&entry.title, &entry.html_content)),
+        ).unwrap();
+    }
+    // Atom requires an `updated` date, even for a feed with no entry yet.
+    let updated = atom_date(version.last_modified.unwrap_or(0));
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{FEED_TITLE}</title>
+<link href="{base_url}/archive"/>
+<link rel="self" href="{base_url}/feed.atom"/>
+<id>{base_url}/archive</id>
+<updated>{updated}</updated>
+<author><name>{FEED_TITLE}</name></author>
+{items}</feed>
+"#
+    );
+    Ok(version
+        .validators(HttpResponse::Ok())
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(body))
+}
+
+impl FeedVersion {
+    fn etag(&self) -> EntityTag {
+        EntityTag::new_strong(format!("{}-{}", self.changed_at.unwrap_or(0), self.n_visible))
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.last_modified.map(|t| UNIX_EPOCH + Duration::from_secs(t.max(0) as u64))
+    }
+
+    /// Whether the client already holds this version of the feed. As HTTP
+    /// asks, `If-Modified-Since` only counts when the request has no
+    /// `If-None-Match`.
+    fn is_known_to(&self, request:&HttpRequest) -> bool {
+        match request.get_header::<IfNoneMatch>() {
+            Some(IfNoneMatch::Any) => true,
+            Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&self.etag())),
+            None => match (request.get_header::<IfModifiedSince>(), self.last_modified()) {
+                (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= SystemTime::from(since),
+                _ => false,
+            },
+        }
+    }
+
+    fn validators(&self, mut response:HttpResponseBuilder) -> HttpResponseBuilder {
+        response.insert_header(ETag(self.etag()));
+        if let Some(last_modified) = self.last_modified() {
+            response.insert_header(LastModified(last_modified.into()));
+        }
+        response
+    }
+}
+
+fn timestamp(seconds:i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(seconds, 0).unwrap_or_default()
+}
+
+fn atom_date(seconds:i64) -> String {
+    timestamp(seconds).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn xml_escape(s:&str) -> String {
+    htmlescape::encode_minimal(s)
+}
+
+#[tracing::instrument(
+    name = "Get the feed version",
+    skip(pool)
+)]
+async fn get_feed_version(
+    pool:&PgPool
+) -> Result<FeedVersion,sqlx::Error> {
+    let version = sqlx::query!(
+        r#"
+        SELECT
+            extract(epoch FROM max(greatest(published_at, updated_at)))::BIGINT AS last_modified,
+            (extract(epoch FROM max(greatest(published_at, updated_at))) * 1000000)::BIGINT AS changed_at,
+            count(*) FILTER (WHERE slug IS NOT NULL AND NOT hidden_from_archive) AS "n_visible!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        "#
+    )
+        .fetch_one(pool)
+        .await?;
+    Ok(FeedVersion {
+        last_modified: version.last_modified,
+        changed_at: version.changed_at,
+        n_visible: version.n_visible,
+    })
+}
+
+/// The latest issues of the archive, newest first.
+#[tracing::instrument(
+    name = "Get the feed entries",
+    skip(pool)
+)]
+async fn get_feed_entries(
+    pool:&PgPool
+) -> Result<Vec<FeedEntry>,sqlx::Error> {
+    sqlx::query_as!(
+        FeedEntry,
+        r#"
+        SELECT
+            newsletter_issues_id,
+            slug AS "slug!",
+            title,
+            html_content,
+            extract(epoch FROM published_at)::BIGINT AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        AND slug IS NOT NULL
+        AND NOT hidden_from_archive
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        FEED_LENGTH
+    )
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,196 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{domain::{render_merge_tags, MergeFields}, routes::e500};
+
+
+/// How many issues an archive page lists.
+pub const ARCHIVE_PAGE_SIZE: i64 = 10;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveQuery {
+    page:Option<i64>,
+}
+
+struct ArchivedIssueSummary {
+    slug:String,
+    title:String,
+    published_on:String,
+}
+
+struct ArchivedIssue {
+    title:String,
+    html_content:String,
+    published_on:String,
+}
+
+/// Published issues, newest first, except the ones hidden by an admin.
+#[tracing::instrument(
+    name = "Show the archive",
+    skip(query,pool)
+)]
+pub async fn archive_page(
+    query:web::Query<ArchiveQuery>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    let mut issues = get_archived_issues(&pool, page).await.map_err(e500)?;
+    let has_older = issues.len() as i64 > ARCHIVE_PAGE_SIZE;
+    issues.truncate(ARCHIVE_PAGE_SIZE as usize);
+
+    let mut items = String::new();
+    for issue in &issues {
+        writeln!(
+            items,
+            r#"<li><a href="/archive/{}">{}</a> <small>{}</small></li>"#,
+            issue.slug,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_on,
+        ).unwrap();
+    }
+    let issues_html = if issues.is_empty() {
+        "<p>There are no issues here yet.</p>".to_string()
+    } else {
+        format!("<ul>\n{}</ul>", items)
+    };
+
+    let mut pagination = String::new();
+    if page > 1 {
+        write!(pagination, r#"<a href="/archive?page={}">Newer issues</a> "#, page - 1).unwrap();
+    }
+    if has_older {
+        write!(pagination, r#"<a href="/archive?page={}">Older issues</a>"#, page + 1).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Archive</title>
+                <link rel="alternate" type="application/rss+xml" href="/feed.rss">
+                <link rel="alternate" type="application/atom+xml" href="/feed.atom">
+                </head>
+                <body>
+                <h1>Past issues</h1>
+                <form action="/archive/search" method="get">
+                <input type="search" name="q">
+                <button type="submit">Search</button>
+                </form>
+                {issues_html}
+                <p>{pagination}</p>
+                <p>Follow us in a feed reader: <a href="/feed.rss">RSS</a> or <a href="/feed.atom">Atom</a>.</p>
+                <p><a href="/">&lt;- Home</a></p>
+                </body>
+                </html>"#
+        )))
+}
+
+#[tracing::instrument(
+    name = "Show an archived issue",
+    skip(pool)
+)]
+pub async fn archived_issue_page(
+    slug:web::Path<String>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue = get_archived_issue(&pool, &slug)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown newsletter issue."))?;
+
+    let content = public_content(&issue.title, &issue.html_content);
+    let title = htmlescape::encode_minimal(&issue.title);
+    let published_on = issue.published_on;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+                </head>
+                <body>
+                <h1>{title}</h1>
+                <p><small>Published on {published_on}</small></p>
+                <article>
+                {content}
+                </article>
+                <p><a href="/archive">&lt;- All issues</a></p>
+                </body>
+                </html>"#
+        )))
+}
+
+/// The HTML part of an issue as anyone may read it. Nobody in particular
+/// reads the archive or the feeds: subscriber tags fall back to their defaults.
+pub fn public_content(title:&str, html_content:&str) -> String {
+    let fields = MergeFields {
+        subscriber_name: None,
+        subscriber_email: "",
+        unsubscribe_url: "",
+        issue_title: title,
+    };
+    render_merge_tags(html_content, &fields, true)
+}
+
+#[tracing::instrument(
+    name = "Get archived issues",
+    skip(pool)
+)]
+async fn get_archived_issues(
+    pool:&PgPool,
+    page:i64
+) -> Result<Vec<ArchivedIssueSummary>,sqlx::Error> {
+    // One more than a page, to know whether there is an older page.
+    sqlx::query_as!(
+        ArchivedIssueSummary,
+        r#"
+        SELECT
+            slug AS "slug!",
+            title,
+            to_char(published_at AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS "published_on!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        AND slug IS NOT NULL
+        AND NOT hidden_from_archive
+        ORDER BY published_at DESC, slug
+        LIMIT $1 OFFSET $2
+        "#,
+        ARCHIVE_PAGE_SIZE + 1,
+        (page - 1) * ARCHIVE_PAGE_SIZE
+    )
+        .fetch_all(pool)
+        .await
+}
+
+#[tracing::instrument(
+    name = "Get an archived issue",
+    skip(pool)
+)]
+async fn get_archived_issue(
+    pool:&PgPool,
+    slug:&str
+) -> Result<Option<ArchivedIssue>,sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            title,
+            html_content,
+            to_char(published_at AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS "published_on!"
+        FROM newsletter_issues
+        WHERE slug = $1
+        AND published_at IS NOT NULL
+        AND NOT hidden_from_archive
+        "#,
+        slug
+    )
+        .fetch_optional(pool)
+        .await
+}
@@ -0,0 +1,7 @@
+mod get;
+mod feed;
+mod search;
+
+pub use get::*;
+pub use feed::*;
+pub use search::*;
@@ -0,0 +1,194 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::routes::e500;
+
+
+/// How many results a search shows, best first.
+pub const SEARCH_LIMIT: i64 = 20;
+/// Control characters stand for the matched words in a snippet. Unlike
+/// `<mark>`, they come through HTML escaping unchanged, so snippets are
+/// escaped first and marked after.
+const START_MATCH: char = '\u{2}';
+const STOP_MATCH: char = '\u{3}';
+
+#[derive(serde::Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q:String,
+}
+
+struct SearchResult {
+    newsletter_issues_id:Uuid,
+    slug:Option<String>,
+    title:String,
+    delivery_state:String,
+    snippet:String,
+}
+
+#[tracing::instrument(
+    name = "Search the archive",
+    skip(query,pool),
+    fields(q = %query.q)
+)]
+pub async fn archive_search_page(
+    query:web::Query<SearchQuery>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let results = search_issues(&pool, &query.q, false).await.map_err(e500)?;
+    let results_html = results_html(&query.q, &results, |result| {
+        format!("/archive/{}", result.slug.as_deref().unwrap_or_default())
+    });
+    let q = htmlescape::encode_attribute(&query.q);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Search the archive</title>
+                </head>
+                <body>
+                <h1>Search past issues</h1>
+                <form action="/archive/search" method="get">
+                <input type="search" name="q" value="{q}">
+                <button type="submit">Search</button>
+                </form>
+                {results_html}
+                <p><a href="/archive">&lt;- All issues</a></p>
+                </body>
+                </html>"#
+        )))
+}
+
+/// Like the public search, but drafts, scheduled and hidden issues are found too.
+#[tracing::instrument(
+    name = "Search every issue",
+    skip(query,pool),
+    fields(q = %query.q)
+)]
+pub async fn admin_search_page(
+    query:web::Query<SearchQuery>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let results = search_issues(&pool, &query.q, true).await.map_err(e500)?;
+    let results_html = results_html(&query.q, &results, |result| match result.delivery_state.as_str() {
+        "draft" => format!("/admin/drafts/{}", result.newsletter_issues_id),
+        _ => format!("/admin/issues/{}", result.newsletter_issues_id),
+    });
+    let q = htmlescape::encode_attribute(&query.q);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Search issues</title>
+                </head>
+                <body>
+                <h1>Search issues</h1>
+                <form action="/admin/search" method="get">
+                <input type="search" name="q" value="{q}">
+                <button type="submit">Search</button>
+                </form>
+                {results_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        )))
+}
+
+fn results_html(
+    q:&str,
+    results:&[SearchResult],
+    link:impl Fn(&SearchResult) -> String
+) -> String {
+    if q.trim().is_empty() {
+        return String::new();
+    }
+    if results.is_empty() {
+        return "<p>No issue matches your search.</p>".to_string();
+    }
+    let mut items = String::new();
+    for result in results {
+        writeln!(
+            items,
+            r#"<li><a href="{}">{}</a> <small>{}</small><p>{}</p></li>"#,
+            link(result),
+            htmlescape::encode_minimal(&result.title),
+            result.delivery_state,
+            highlight(&result.snippet),
+        ).unwrap();
+    }
+    format!("<ol>\n{}</ol>", items)
+}
+
+/// Escapes a snippet, then wraps its matched words in `<mark>`.
+fn highlight(snippet:&str) -> String {
+    htmlescape::encode_minimal(snippet)
+        .replace(START_MATCH, "<mark>")
+        .replace(STOP_MATCH, "</mark>")
+}
+
+/// Ranks the issues whose title or text part match `q`, read as a web search:
+/// `"exact phrase"`, `or` and `-excluded` work as expected.
+#[tracing::instrument(
+    name = "Search issues",
+    skip(pool)
+)]
+async fn search_issues(
+    pool:&PgPool,
+    q:&str,
+    include_unpublished:bool
+) -> Result<Vec<SearchResult>,sqlx::Error> {
+    if q.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    sqlx::query_as!(
+        SearchResult,
+        r#"
+        SELECT
+            i.newsletter_issues_id,
+            i.slug,
+            i.title,
+            i.delivery_state,
+            ts_headline(
+                'english',
+                i.text_content,
+                query,
+                'StartSel=' || $4 || ', StopSel=' || $5 || ', MaxFragments=2, MaxWords=30, MinWords=10'
+            ) AS "snippet!"
+        FROM newsletter_issues i, websearch_to_tsquery('english', $1) query
+        WHERE i.search_vector @@ query
+        AND ($2 OR (i.published_at IS NOT NULL AND i.slug IS NOT NULL AND NOT i.hidden_from_archive))
+        ORDER BY ts_rank(i.search_vector, query) DESC, i.published_at DESC NULLS LAST
+        LIMIT $3
+        "#,
+        q,
+        include_unpublished,
+        SEARCH_LIMIT,
+        START_MATCH.to_string(),
+        STOP_MATCH.to_string()
+    )
+        .fetch_all(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight;
+
+    #[test]
+    fn snippets_are_escaped_before_being_marked() {
+        assert_eq!(
+            highlight("<b>quick</b> \u{2}brown\u{3} fox"),
+            "&lt;b&gt;quick&lt;/b&gt; <mark>brown</mark> fox"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::routes::e500;
+
+
+struct FailedDelivery {
+    newsletter_issues_id:Uuid,
+    title:String,
+    subscriber_email:String,
+    last_error:String,
+    n_attempts:i32,
+    failed_at:String,
+}
+
+#[tracing::instrument(
+    name = "Show failed deliveries",
+    skip(pool,flash_message)
+)]
+pub async fn failed_deliveries_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let failures = get_failed_deliveries(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    let mut current_issue = None;
+    for failure in &failures {
+        if current_issue != Some(failure.newsletter_issues_id) {
+            if current_issue.is_some() {
+                rows.push_str("</table>\n");
+            }
+            current_issue = Some(failure.newsletter_issues_id);
+            writeln!(
+                rows,
+                "<h2>{}</h2>\n<table>\n<tr><th></th><th>Subscriber</th><th>Last error</th><th>Attempts</th><th>Failed at</th></tr>",
+                htmlescape::encode_minimal(&failure.title)
+            ).unwrap();
+        }
+        writeln!(
+            rows,
+            r#"<tr><td><input type="checkbox" name="tasks" value="{}/{}"></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            failure.newsletter_issues_id,
+            htmlescape::encode_attribute(&failure.subscriber_email),
+            htmlescape::encode_minimal(&failure.subscriber_email),
+            htmlescape::encode_minimal(&failure.last_error),
+            failure.n_attempts,
+            failure.failed_at,
+        ).unwrap();
+    }
+    if current_issue.is_some() {
+        rows.push_str("</table>\n");
+    } else {
+        rows.push_str("<p>There are no failed deliveries.</p>\n");
+    }
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Failed deliveries</title>
+                </head>
+                <body>
+                {messages}
+                <form action="/admin/deliveries/failed" method="post">
+                {rows}
+                <button type="submit">Re-enqueue selected</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Get failed deliveries",
+    skip(pool)
+)]
+async fn get_failed_deliveries(
+    pool:&PgPool
+) -> Result<Vec<FailedDelivery>,sqlx::Error> {
+    let rows = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            f.newsletter_issues_id,
+            i.title,
+            f.subscriber_email,
+            f.last_error,
+            f.n_attempts,
+            f.failed_at::TEXT AS "failed_at!"
+        FROM issues_delivery_failures f
+        JOIN newsletter_issues i USING (newsletter_issues_id)
+        ORDER BY i.published_at DESC, f.newsletter_issues_id, f.subscriber_email
+        "#,
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
@@ -0,0 +1,140 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use actix_web_lab::extract::UrlEncodedForm;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{issue_delivery_work::notify_new_tasks, routes::{e400, e500, see_other}};
+
+
+#[derive(serde::Deserialize)]
+pub struct RequeueFormData {
+    #[serde(default)]
+    tasks:Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Re-enqueue failed deliveries",
+    skip(form,pool)
+)]
+pub async fn requeue_failed_deliveries(
+    form:UrlEncodedForm<RequeueFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let tasks = form.0.tasks
+        .iter()
+        .map(|t| parse_task(t))
+        .collect::<Result<Vec<_>,_>>()
+        .map_err(e400)?;
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let mut n_requeued = 0;
+    let mut n_cancelled = 0;
+    for (issue_id,email) in &tasks {
+        match requeue_task(&mut transaction, *issue_id, email).await.map_err(e500)? {
+            RequeueOutcome::Requeued => n_requeued += 1,
+            RequeueOutcome::IssueCancelled => n_cancelled += 1,
+            RequeueOutcome::Skipped => {}
+        }
+    }
+    notify_new_tasks(&mut transaction).await.map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    FlashMessage::info(format!("Re-enqueued {} deliveries.",n_requeued)).send();
+    if n_cancelled > 0 {
+        FlashMessage::warning(format!(
+            "{} deliveries belong to cancelled issues and were not re-enqueued.",
+            n_cancelled
+        )).send();
+    }
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+enum RequeueOutcome {
+    Requeued,
+    /// Nothing is sent for a cancelled issue, so the delivery stays failed.
+    IssueCancelled,
+    /// The delivery is no longer failed, or is already queued.
+    Skipped,
+}
+
+/// Checkbox values are `{newsletter_issues_id}/{subscriber_email}`.
+fn parse_task(task:&str) -> Result<(Uuid,String),anyhow::Error> {
+    let (issue_id,email) = task
+        .split_once('/')
+        .ok_or_else(|| anyhow::anyhow!("Invalid delivery: {}",task))?;
+    Ok((Uuid::parse_str(issue_id)?,email.to_owned()))
+}
+
+#[tracing::instrument(skip_all)]
+async fn requeue_task(
+    transaction:&mut PgConnection,
+    newsletter_issues_id:Uuid,
+    subscriber_email:&str
+) -> Result<RequeueOutcome,sqlx::Error> {
+    // Locked, so that the issue is not cancelled while its delivery is queued.
+    let issue = sqlx::query!(
+        r#"
+        SELECT delivery_state
+        FROM newsletter_issues
+        WHERE newsletter_issues_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issues_id
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    match issue {
+        None => return Ok(RequeueOutcome::Skipped),
+        Some(issue) if issue.delivery_state == "cancelled" => return Ok(RequeueOutcome::IssueCancelled),
+        Some(_) => {}
+    }
+
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM issues_delivery_failures
+        WHERE
+        newsletter_issues_id = $1 AND
+        subscriber_email = $2
+        "#,
+        newsletter_issues_id,
+        subscriber_email
+    )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Ok(RequeueOutcome::Skipped);
+    }
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO issues_delivery_queue (
+            newsletter_issues_id,
+            subscriber_email
+        )
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issues_id,
+        subscriber_email
+    )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    // A finished issue is sending again; paused ones stay put.
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'sending'
+        WHERE newsletter_issues_id = $1 AND delivery_state = 'completed'
+        "#,
+        newsletter_issues_id
+    )
+        .execute(&mut *transaction)
+        .await?;
+    match inserted {
+        0 => Ok(RequeueOutcome::Skipped),
+        _ => Ok(RequeueOutcome::Requeued),
+    }
+}
@@ -0,0 +1,217 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::routes::{e500, email_template_options, get_issue_list_ids, list_checkboxes, segment_options};
+
+
+struct DraftSummary {
+    newsletter_issues_id:Uuid,
+    title:String,
+    updated_at:String,
+}
+
+struct Draft {
+    title:String,
+    email_template_id:Option<Uuid>,
+    segment_id:Option<Uuid>,
+    markdown_content:String,
+    text_content:String,
+    html_content:String,
+}
+
+#[tracing::instrument(
+    name = "Show the drafts",
+    skip(pool,flash_message)
+)]
+pub async fn drafts_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for draft in &drafts {
+        let title = match draft.title.trim() {
+            "" => "(untitled)".to_string(),
+            title => htmlescape::encode_minimal(title),
+        };
+        writeln!(
+            rows,
+            r#"<tr><td><a href="/admin/drafts/{}">{}</a></td><td>{}</td></tr>"#,
+            draft.newsletter_issues_id,
+            title,
+            draft.updated_at,
+        ).unwrap();
+    }
+    let drafts_html = if drafts.is_empty() {
+        "<p>There are no drafts.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Title</th><th>Last saved</th></tr>\n{}</table>",
+            rows
+        )
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Drafts</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Drafts</h1>
+                {drafts_html}
+                <p><a href="/admin/newsletter">Write a new issue</a></p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Show the draft editor",
+    skip(pool,flash_message)
+)]
+pub async fn edit_draft_form(
+    draft_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let draft_id = draft_id.into_inner();
+    let draft = get_draft(&pool, draft_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown draft."))?;
+    let title = htmlescape::encode_attribute(&draft.title);
+    let markdown_content = htmlescape::encode_minimal(&draft.markdown_content);
+    let text_content = htmlescape::encode_minimal(&draft.text_content);
+    let html_content = htmlescape::encode_minimal(&draft.html_content);
+    let idempotency_key = Uuid::new_v4().to_string();
+    let template_options = email_template_options(&pool, draft.email_template_id).await.map_err(e500)?;
+    let list_ids = get_issue_list_ids(&pool, draft_id).await.map_err(e500)?;
+    let list_checkboxes = list_checkboxes(&pool, &list_ids).await.map_err(e500)?;
+    let segment_options = segment_options(&pool, draft.segment_id).await.map_err(e500)?;
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Edit draft</title>
+                </head>
+                <body>
+                {messages}
+                <form action="/admin/drafts/{draft_id}" method="post">
+                <label> Title
+                <input type="text" name="title" value="{title}">
+                </label>
+                <label> Content markdown
+                <textarea rows="12" cols="80" name="markdown_content">{markdown_content}</textarea>
+                </label>
+                <p>The HTML and text parts are rendered from the Markdown unless you fill them in yourself.</p>
+                <p>Merge tags are filled in for every subscriber: {{{{ subscriber.name }}}}, {{{{ subscriber.email }}}},
+                {{{{ unsubscribe_url }}}} and {{{{ issue.title }}}}. Give one a fallback with {{{{ subscriber.name | default: "friend" }}}}.</p>
+                <label> Content text
+                <textarea rows="12" cols="80" name="text_content">{text_content}</textarea>
+                </label>
+                <label> Content html
+                <textarea rows="12" cols="80" name="html_content">{html_content}</textarea>
+                </label>
+                <label> Template
+                <select name="email_template_id">{template_options}</select>
+                </label>
+                {list_checkboxes}
+                <label> Segment
+                <select name="segment_id">{segment_options}</select>
+                </label>
+                <button type="submit"> Save draft </button>
+                <button type="submit" formaction="/admin/newsletter/preview" formtarget="_blank"> Preview </button>
+                </form>
+                <p>The last saved version is the one that gets published.</p>
+                <form action="/admin/drafts/{draft_id}/publish" method="post">
+                <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                <label> Send at (UTC, leave empty to send now)
+                <input type="datetime-local" name="scheduled_for">
+                </label>
+                <button type="submit"> Publish draft </button>
+                </form>
+                <p><a href="/admin/drafts">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Get drafts",
+    skip(pool)
+)]
+async fn get_drafts(
+    pool:&PgPool
+) -> Result<Vec<DraftSummary>,sqlx::Error> {
+    sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT
+            newsletter_issues_id,
+            title,
+            updated_at::TEXT AS "updated_at!"
+        FROM newsletter_issues
+        WHERE delivery_state = 'draft'
+        ORDER BY updated_at DESC
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
+
+#[tracing::instrument(
+    name = "Get a draft",
+    skip(pool)
+)]
+async fn get_draft(
+    pool:&PgPool,
+    draft_id:Uuid
+) -> Result<Option<Draft>,sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT title, email_template_id, segment_id, markdown_content, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issues_id = $1 AND delivery_state = 'draft'
+        "#,
+        draft_id
+    )
+        .fetch_optional(pool)
+        .await
+}
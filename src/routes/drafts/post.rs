@@ -0,0 +1,169 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use actix_web_lab::extract::UrlEncodedForm;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{idempotency::{save_response, try_processing, IdempotencyKey, NextAction}, middleware::UserID, routes::{e400, e500, insert_newsletter_issue, parse_list_ids, parse_segment_id, parse_send_time, parse_template_id, publish_issue, lock_segment, lock_template, save_issue_lists, see_other, UNKNOWN_LIST, UNKNOWN_SEGMENT, UNKNOWN_TEMPLATE}};
+
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title:String,
+    html_content:String,
+    text_content:String,
+    #[serde(default)]
+    markdown_content:String,
+    #[serde(default)]
+    email_template_id:String,
+    #[serde(default)]
+    list_ids:Vec<String>,
+    #[serde(default)]
+    segment_id:String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishDraftFormData {
+    idempotency_key:String,
+    /// Left empty to send the draft straight away.
+    #[serde(default)]
+    scheduled_for:String,
+}
+
+#[tracing::instrument(
+    name = "Create a draft",
+    skip(form,pool)
+)]
+pub async fn create_draft(
+    form:UrlEncodedForm<DraftFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let DraftFormData {title,html_content,text_content,markdown_content,email_template_id,list_ids,segment_id} = form.0;
+    let email_template_id = parse_template_id(&email_template_id).map_err(e400)?;
+    let list_ids = parse_list_ids(&list_ids).map_err(e400)?;
+    let segment_id = parse_segment_id(&segment_id).map_err(e400)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    if !lock_segment(&mut transaction, segment_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_SEGMENT));
+    }
+    if !lock_template(&mut transaction, email_template_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_TEMPLATE));
+    }
+    let draft_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content, &markdown_content, email_template_id, segment_id)
+        .await
+        .map_err(e500)?;
+    if !save_issue_lists(&mut transaction, draft_id, &list_ids).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_LIST));
+    }
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(
+    name = "Update a draft",
+    skip(form,pool)
+)]
+pub async fn update_draft(
+    draft_id:web::Path<Uuid>,
+    form:UrlEncodedForm<DraftFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let email_template_id = parse_template_id(&form.email_template_id).map_err(e400)?;
+    let list_ids = parse_list_ids(&form.list_ids).map_err(e400)?;
+    let segment_id = parse_segment_id(&form.segment_id).map_err(e400)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    if !lock_segment(&mut transaction, segment_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_SEGMENT));
+    }
+    if !lock_template(&mut transaction, email_template_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_TEMPLATE));
+    }
+    let saved = save_draft(&mut transaction, draft_id, &form.0, email_template_id, segment_id).await.map_err(e500)?;
+    if saved && !save_issue_lists(&mut transaction, draft_id, &list_ids).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_LIST));
+    }
+    transaction.commit().await.map_err(e500)?;
+    if saved {
+        FlashMessage::info("The draft has been saved.").send();
+        Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+    } else {
+        FlashMessage::error("Only a draft can be edited.").send();
+        Ok(see_other("/admin/drafts"))
+    }
+}
+
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip(form,pool,user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_draft(
+    draft_id:web::Path<Uuid>,
+    form:web::Form<PublishDraftFormData>,
+    pool:web::Data<PgPool>,
+    user_id:web::ReqData<UserID>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let PublishDraftFormData {idempotency_key,scheduled_for} = form.0;
+    let idempotency_key:IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let scheduled_for = match scheduled_for.trim() {
+        "" => None,
+        s => Some(parse_send_time(s).map_err(e400)?),
+    };
+
+    let user_id = user_id.into_inner();
+    let mut transaction: Transaction<'static, Postgres> = match try_processing(&pool, user_id, &idempotency_key).await.map_err(e500)? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            return Ok(saved_response)
+        }
+    };
+
+    match publish_issue(&mut transaction, draft_id, scheduled_for).await.map_err(e500)? {
+        Some(outcome) => outcome.flash_message(draft_id).send(),
+        None => FlashMessage::error("Only a draft can be published.").send(),
+    }
+    let response = see_other("/admin/drafts");
+    let response = save_response(transaction, *user_id, response, &idempotency_key)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
+
+/// Returns whether `draft_id` is still a draft, and hence was saved.
+#[tracing::instrument(skip(connection,draft))]
+async fn save_draft(
+    connection:&mut PgConnection,
+    draft_id:Uuid,
+    draft:&DraftFormData,
+    email_template_id:Option<Uuid>,
+    segment_id:Option<Uuid>
+) -> Result<bool,sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            markdown_content = $5,
+            email_template_id = $6,
+            segment_id = $7,
+            updated_at = now()
+        WHERE newsletter_issues_id = $1 AND delivery_state = 'draft'
+        "#,
+        draft_id,
+        draft.title,
+        draft.text_content,
+        draft.html_content,
+        draft.markdown_content,
+        email_template_id,
+        segment_id
+    )
+        .execute(connection)
+        .await?
+        .rows_affected();
+    Ok(updated > 0)
+}
@@ -0,0 +1,353 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::routes::e500;
+
+
+#[derive(Serialize)]
+pub struct IssueProgress {
+    title:String,
+    state:String,
+    total:i64,
+    pending:i64,
+    sent:i64,
+    failed:i64,
+    cancelled:i64,
+    /// `None` while the issue is still scheduled.
+    started_at:Option<String>,
+    /// Extrapolated from the pace so far; `None` until the first delivery
+    /// is done and whenever the issue is not being sent.
+    estimated_completion:Option<String>,
+    /// `None` until the issue is published.
+    slug:Option<String>,
+    hidden_from_archive:bool,
+}
+
+struct ScheduledIssue {
+    newsletter_issues_id:Uuid,
+    title:String,
+    scheduled_for:String,
+}
+
+struct IssueFailure {
+    subscriber_email:String,
+    last_error:String,
+    n_attempts:i32,
+    failed_at:String,
+}
+
+#[tracing::instrument(
+    name = "Show the delivery progress of an issue",
+    skip(pool,flash_message)
+)]
+pub async fn issue_progress_page(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let issue_id = issue_id.into_inner();
+    let progress = get_issue_progress(&pool, issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown newsletter issue."))?;
+    let failures = get_issue_failures(&pool, issue_id).await.map_err(e500)?;
+
+    let mut failure_rows = String::new();
+    for failure in &failures {
+        writeln!(
+            failure_rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&failure.subscriber_email),
+            htmlescape::encode_minimal(&failure.last_error),
+            failure.n_attempts,
+            failure.failed_at,
+        ).unwrap();
+    }
+    let failures_html = if failures.is_empty() {
+        "<p>No delivery has failed.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Subscriber</th><th>Last error</th><th>Attempts</th><th>Failed at</th></tr>\n{}</table>",
+            failure_rows
+        )
+    };
+
+    let title = htmlescape::encode_minimal(&progress.title);
+    let IssueProgress {state, total, pending, sent, failed, cancelled, started_at, ..} = &progress;
+    let started_at = started_at.as_deref().unwrap_or("-");
+    let estimated_completion = progress.estimated_completion.as_deref().unwrap_or("-");
+
+    let action = |name:&str, label:&str| format!(
+        r#"<form action="/admin/issues/{issue_id}/{name}" method="post"><button type="submit">{label}</button></form>"#
+    );
+    let actions = match state.as_str() {
+        "sending" => action("pause", "Pause") + &action("cancel", "Cancel"),
+        "paused" => action("resume", "Resume") + &action("cancel", "Cancel"),
+        _ => String::new(),
+    };
+    let archive = match (&progress.slug, progress.hidden_from_archive) {
+        (_, true) => format!(
+            "<p>Hidden from the public archive.</p>{}",
+            action("archive/show", "Show in the archive")
+        ),
+        (Some(slug), false) => format!(
+            r#"<p>Public archive: <a href="/archive/{slug}">/archive/{slug}</a></p>{}"#,
+            action("archive/hide", "Hide from the archive")
+        ),
+        (None, false) => format!(
+            "<p>Listed in the public archive once sent.</p>{}",
+            action("archive/hide", "Hide from the archive")
+        ),
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Delivery of {title}</title>
+                </head>
+                <body>
+                {messages}
+                <h1>{title}</h1>
+                <p>Delivery: <span id="state">{state}</span></p>
+                {actions}
+                {archive}
+                <ul>
+                <li>Recipients: <span id="total">{total}</span></li>
+                <li>Pending: <span id="pending">{pending}</span></li>
+                <li>Sent: <span id="sent">{sent}</span></li>
+                <li>Failed: <span id="failed">{failed}</span></li>
+                <li>Cancelled: <span id="cancelled">{cancelled}</span></li>
+                <li>Started at: {started_at}</li>
+                <li>Estimated completion: <span id="estimated_completion">{estimated_completion}</span></li>
+                </ul>
+                <h2>Failures</h2>
+                {failures_html}
+                <p><a href="/admin/deliveries/failed">Re-enqueue failed deliveries</a></p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                <script>
+                const refresh = async () => {{
+                    const response = await fetch("/admin/issues/{issue_id}/progress");
+                    const progress = await response.json();
+                    for (const field of ["state", "total", "pending", "sent", "failed", "cancelled"]) {{
+                        document.getElementById(field).textContent = progress[field];
+                    }}
+                    document.getElementById("estimated_completion").textContent =
+                        progress.estimated_completion ?? "-";
+                    if (progress.state === "sending") {{
+                        setTimeout(refresh, 2000);
+                    }}
+                }};
+                if ("{state}" === "sending") {{
+                    setTimeout(refresh, 2000);
+                }}
+                </script>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Show the scheduled issues",
+    skip(pool,flash_message)
+)]
+pub async fn scheduled_issues_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for issue in &issues {
+        let id = issue.newsletter_issues_id;
+        writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td><td>
+            <form action="/admin/issues/{id}/reschedule" method="post">
+            <input type="datetime-local" name="scheduled_for">
+            <button type="submit">Reschedule</button>
+            </form>
+            <form action="/admin/issues/{id}/unschedule" method="post">
+            <button type="submit">Cancel</button>
+            </form>
+            </td></tr>"#,
+            htmlescape::encode_minimal(&issue.title),
+            issue.scheduled_for,
+        ).unwrap();
+    }
+    let issues_html = if issues.is_empty() {
+        "<p>No issue is scheduled.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Title</th><th>Scheduled for</th><th></th></tr>\n{}</table>",
+            rows
+        )
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Scheduled issues</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Scheduled issues</h1>
+                <p>Send times are in UTC.</p>
+                {issues_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Get the delivery progress of an issue as JSON",
+    skip(pool)
+)]
+pub async fn issue_progress(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let progress = get_issue_progress(&pool, issue_id.into_inner())
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown newsletter issue."))?;
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+#[tracing::instrument(
+    name = "Get issue progress",
+    skip(pool)
+)]
+async fn get_issue_progress(
+    pool:&PgPool,
+    issue_id:Uuid
+) -> Result<Option<IssueProgress>,sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH counts AS (
+            SELECT
+                (SELECT count(*) FROM issues_delivery_queue
+                    WHERE newsletter_issues_id = $1) AS pending,
+                (SELECT count(*) FROM deliveries
+                    WHERE newsletter_issues_id = $1
+                    AND status NOT IN ('retrying', 'failed')) AS sent,
+                (SELECT count(*) FROM issues_delivery_failures
+                    WHERE newsletter_issues_id = $1) AS failed
+        )
+        SELECT
+            i.title,
+            i.delivery_state,
+            i.n_cancelled_deliveries,
+            i.published_at::TEXT AS started_at,
+            i.slug,
+            i.hidden_from_archive,
+            c.pending AS "pending!",
+            c.sent AS "sent!",
+            c.failed AS "failed!",
+            CASE WHEN i.delivery_state = 'sending' AND c.pending > 0 AND c.sent + c.failed > 0 THEN
+                (now() + (now() - i.published_at) * (c.pending::float8 / (c.sent + c.failed)))::TEXT
+            END AS estimated_completion
+        FROM newsletter_issues i, counts c
+        WHERE i.newsletter_issues_id = $1
+        "#,
+        issue_id
+    )
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|r| IssueProgress {
+        title: r.title,
+        state: r.delivery_state,
+        total: r.pending + r.sent + r.failed + r.n_cancelled_deliveries as i64,
+        pending: r.pending,
+        sent: r.sent,
+        failed: r.failed,
+        cancelled: r.n_cancelled_deliveries as i64,
+        started_at: r.started_at,
+        estimated_completion: r.estimated_completion,
+        slug: r.slug,
+        hidden_from_archive: r.hidden_from_archive,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get scheduled issues",
+    skip(pool)
+)]
+async fn get_scheduled_issues(
+    pool:&PgPool
+) -> Result<Vec<ScheduledIssue>,sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT
+            newsletter_issues_id,
+            title,
+            to_char(scheduled_for AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS "scheduled_for!"
+        FROM newsletter_issues
+        WHERE delivery_state = 'scheduled'
+        ORDER BY scheduled_for
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
+
+#[tracing::instrument(
+    name = "Get the failed deliveries of an issue",
+    skip(pool)
+)]
+async fn get_issue_failures(
+    pool:&PgPool,
+    issue_id:Uuid
+) -> Result<Vec<IssueFailure>,sqlx::Error> {
+    sqlx::query_as!(
+        IssueFailure,
+        r#"
+        SELECT
+            subscriber_email,
+            last_error,
+            n_attempts,
+            failed_at::TEXT AS "failed_at!"
+        FROM issues_delivery_failures
+        WHERE newsletter_issues_id = $1
+        ORDER BY failed_at, subscriber_email
+        "#,
+        issue_id
+    )
+        .fetch_all(pool)
+        .await
+}
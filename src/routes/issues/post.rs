@@ -0,0 +1,252 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{issue_delivery_work::notify_new_tasks, routes::{e500, parse_send_time, see_other}};
+
+
+#[tracing::instrument(
+    name = "Pause the delivery of an issue",
+    skip(pool)
+)]
+pub async fn pause_issue(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut connection = pool.acquire().await.map_err(e500)?;
+    if change_delivery_state(&mut connection, issue_id, "sending", "paused").await.map_err(e500)? {
+        FlashMessage::info("The delivery has been paused.").send();
+    } else {
+        FlashMessage::error("Only an issue that is being sent can be paused.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(
+    name = "Resume the delivery of an issue",
+    skip(pool)
+)]
+pub async fn resume_issue(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    if change_delivery_state(&mut transaction, issue_id, "paused", "sending").await.map_err(e500)? {
+        notify_new_tasks(&mut transaction).await.map_err(e500)?;
+        FlashMessage::info("The delivery has been resumed.").send();
+    } else {
+        FlashMessage::error("Only a paused issue can be resumed.").send();
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(
+    name = "Cancel the delivery of an issue",
+    skip(pool)
+)]
+pub async fn cancel_issue(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    match cancel_delivery(&mut transaction, issue_id).await.map_err(e500)? {
+        Some(n_cancelled) => {
+            FlashMessage::info(format!(
+                "The delivery has been cancelled. {} emails were never sent.",
+                n_cancelled
+            )).send();
+        }
+        None => {
+            FlashMessage::error("Only an issue that is being sent or paused can be cancelled.").send();
+        }
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    scheduled_for:String,
+}
+
+#[tracing::instrument(
+    name = "Reschedule an issue",
+    skip(form,pool)
+)]
+pub async fn reschedule_issue(
+    issue_id:web::Path<Uuid>,
+    form:web::Form<RescheduleFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let scheduled_for = match parse_send_time(form.0.scheduled_for.trim()) {
+        Ok(t) => t,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e.to_string())).send();
+            return Ok(see_other("/admin/issues/scheduled"));
+        }
+    };
+    let rescheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2::TEXT::timestamptz
+        WHERE newsletter_issues_id = $1 AND delivery_state = 'scheduled'
+        "#,
+        issue_id.into_inner(),
+        scheduled_for.to_rfc3339()
+    )
+        .execute(pool.get_ref())
+        .await
+        .map_err(e500)?
+        .rows_affected();
+    if rescheduled > 0 {
+        FlashMessage::info(format!(
+            "The issue has been rescheduled for {}.",
+            scheduled_for.format("%Y-%m-%d %H:%M UTC")
+        )).send();
+    } else {
+        FlashMessage::error("Only a scheduled issue can be rescheduled.").send();
+    }
+    Ok(see_other("/admin/issues/scheduled"))
+}
+
+#[tracing::instrument(
+    name = "Cancel a scheduled issue",
+    skip(pool)
+)]
+pub async fn unschedule_issue(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut connection = pool.acquire().await.map_err(e500)?;
+    if change_delivery_state(&mut connection, issue_id.into_inner(), "scheduled", "cancelled").await.map_err(e500)? {
+        FlashMessage::info("The scheduled issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("Only a scheduled issue can be cancelled here.").send();
+    }
+    Ok(see_other("/admin/issues/scheduled"))
+}
+
+#[tracing::instrument(
+    name = "Hide an issue from the archive",
+    skip(pool)
+)]
+pub async fn hide_from_archive(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    set_hidden_from_archive(&pool, issue_id, true).await.map_err(e500)?;
+    FlashMessage::info("The issue is hidden from the public archive.").send();
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(
+    name = "Show an issue in the archive",
+    skip(pool)
+)]
+pub async fn show_in_archive(
+    issue_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    set_hidden_from_archive(&pool, issue_id, false).await.map_err(e500)?;
+    FlashMessage::info("The issue is listed in the public archive once it has been sent.").send();
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+/// Also touches `updated_at`, which the feeds report as their last modification.
+#[tracing::instrument(skip(pool))]
+async fn set_hidden_from_archive(
+    pool:&PgPool,
+    issue_id:Uuid,
+    hidden:bool
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET hidden_from_archive = $2, updated_at = now()
+        WHERE newsletter_issues_id = $1
+        "#,
+        issue_id,
+        hidden
+    )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Returns whether the issue was in state `from`.
+#[tracing::instrument(skip(transaction))]
+async fn change_delivery_state(
+    transaction:&mut PgConnection,
+    issue_id:Uuid,
+    from:&str,
+    to:&str
+) -> Result<bool,sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = $3
+        WHERE newsletter_issues_id = $1 AND delivery_state = $2
+        "#,
+        issue_id,
+        from,
+        to
+    )
+        .execute(transaction)
+        .await?
+        .rows_affected();
+    Ok(updated > 0)
+}
+
+/// Drops the remaining deliveries of a sending or paused issue and returns
+/// how many were dropped, or `None` if the issue could not be cancelled.
+#[tracing::instrument(skip(transaction))]
+async fn cancel_delivery(
+    transaction:&mut PgConnection,
+    issue_id:Uuid
+) -> Result<Option<u64>,sqlx::Error> {
+    let cancellable = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id
+        FROM newsletter_issues
+        WHERE newsletter_issues_id = $1
+        AND delivery_state IN ('sending', 'paused')
+        FOR NO KEY UPDATE
+        "#,
+        issue_id
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    if cancellable.is_none() {
+        return Ok(None);
+    }
+
+    // Waits for any batch a worker is sending right now to be committed.
+    // `NO KEY UPDATE` above lets that worker still take the foreign key
+    // locks it needs to record its deliveries.
+    let n_cancelled = sqlx::query!(
+        r#"DELETE FROM issues_delivery_queue WHERE newsletter_issues_id = $1"#,
+        issue_id
+    )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'cancelled', n_cancelled_deliveries = $2
+        WHERE newsletter_issues_id = $1
+        "#,
+        issue_id,
+        n_cancelled as i32
+    )
+        .execute(&mut *transaction)
+        .await?;
+    Ok(Some(n_cancelled))
+}
@@ -0,0 +1,172 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::{routes::e500, startup::ApplicationBaseUrl};
+
+
+struct ListSummary {
+    list_id:Uuid,
+    name:String,
+    slug:String,
+    n_confirmed:i64,
+    n_pending:i64,
+}
+
+#[tracing::instrument(
+    name = "Show the lists",
+    skip(pool,base_url,flash_message)
+)]
+pub async fn lists_page(
+    pool:web::Data<PgPool>,
+    base_url:web::Data<ApplicationBaseUrl>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for list in &lists {
+        // The form a publication embeds on its site to grow this list.
+        let subscribe_form = format!(
+            r#"<form action="{}/subscriptions" method="post">
+<input type="hidden" name="list" value="{}">
+<input type="text" name="name" placeholder="Name">
+<input type="email" name="email" placeholder="Email">
+<button type="submit">Subscribe</button>
+</form>"#,
+            base_url.0,
+            list.slug
+        );
+        let id = list.list_id;
+        writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><pre>{}</pre></td><td>
+            <form action="/admin/lists/{id}/delete" method="post">
+            <button type="submit">Delete</button>
+            </form>
+            </td></tr>"#,
+            htmlescape::encode_minimal(&list.name),
+            list.slug,
+            list.n_confirmed,
+            list.n_pending,
+            htmlescape::encode_minimal(&subscribe_form),
+        ).unwrap();
+    }
+    let lists_html = if lists.is_empty() {
+        "<p>There are no lists. Issues go to every confirmed subscriber.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Name</th><th>Slug</th><th>Confirmed</th><th>Pending</th><th>Subscribe form</th><th></th></tr>\n{}</table>",
+            rows
+        )
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Lists</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Lists</h1>
+                {lists_html}
+                <form action="/admin/lists" method="post">
+                <label> Name
+                <input type="text" name="name">
+                </label>
+                <button type="submit"> Create list </button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+/// The `list_ids` checkboxes of an issue form, with `selected` checked.
+#[tracing::instrument(
+    name = "Get list checkboxes",
+    skip(pool)
+)]
+pub async fn list_checkboxes(
+    pool:&PgPool,
+    selected:&[Uuid]
+) -> Result<String,sqlx::Error> {
+    let lists = get_lists(pool).await?;
+    if lists.is_empty() {
+        return Ok(String::new());
+    }
+    let mut checkboxes = String::from(
+        "<fieldset>\n<legend>Send to (none checked: every confirmed subscriber)</legend>\n"
+    );
+    for list in &lists {
+        writeln!(
+            checkboxes,
+            r#"<label><input type="checkbox" name="list_ids" value="{}"{}> {}</label>"#,
+            list.list_id,
+            if selected.contains(&list.list_id) { " checked" } else { "" },
+            htmlescape::encode_minimal(&list.name),
+        ).unwrap();
+    }
+    checkboxes.push_str("</fieldset>");
+    Ok(checkboxes)
+}
+
+#[tracing::instrument(
+    name = "Get the lists of an issue",
+    skip(pool)
+)]
+pub async fn get_issue_list_ids(
+    pool:&PgPool,
+    issue_id:Uuid
+) -> Result<Vec<Uuid>,sqlx::Error> {
+    let lists = sqlx::query!(
+        r#"SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issues_id = $1"#,
+        issue_id
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(lists.into_iter().map(|l| l.list_id).collect())
+}
+
+#[tracing::instrument(
+    name = "Get lists",
+    skip(pool)
+)]
+async fn get_lists(
+    pool:&PgPool
+) -> Result<Vec<ListSummary>,sqlx::Error> {
+    sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.slug,
+            count(*) FILTER (WHERE m.status = 'confirmed') AS "n_confirmed!",
+            count(*) FILTER (WHERE m.status = 'pending_confirmations') AS "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,167 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::routes::{e500, see_other, slugify};
+
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    name:String,
+}
+
+#[tracing::instrument(
+    name = "Create a list",
+    skip(form,pool)
+)]
+pub async fn create_list(
+    form:web::Form<ListFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let name = form.0.name.trim().to_string();
+    if name.is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name, slug)
+        VALUES ($1, $2, $3)
+        "#,
+        Uuid::new_v4(),
+        name,
+        slugify(&name)
+    )
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(_) => FlashMessage::info("The list has been created.").send(),
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_unique_violation()) => {
+            FlashMessage::error(format!(
+                "A list named {} already exists.",
+                htmlescape::encode_minimal(&name)
+            )).send()
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+/// Lists still targeted by a draft or a scheduled issue are kept: without
+/// them the issue would go to every confirmed subscriber. Sent issues stop
+/// recording the list.
+#[tracing::instrument(
+    name = "Delete a list",
+    skip(pool)
+)]
+pub async fn delete_list(
+    list_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let list_id = list_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Locked, so that none of them is published while the list goes away.
+    let n_pending = sqlx::query!(
+        r#"
+        SELECT i.newsletter_issues_id
+        FROM newsletter_issues i
+        JOIN newsletter_issue_lists l USING (newsletter_issues_id)
+        WHERE l.list_id = $1
+        AND i.delivery_state IN ('draft', 'scheduled')
+        FOR UPDATE OF i
+        "#,
+        list_id
+    )
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .len();
+    if n_pending > 0 {
+        FlashMessage::error(format!(
+            "The list is still used by {} issues that have not been sent yet.",
+            n_pending
+        )).send();
+        return Ok(see_other("/admin/lists"));
+    }
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_lists l
+        USING newsletter_issues i
+        WHERE l.newsletter_issues_id = i.newsletter_issues_id
+        AND l.list_id = $1
+        AND i.delivery_state NOT IN ('draft', 'scheduled')
+        "#,
+        list_id
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    let result = sqlx::query!(
+        r#"DELETE FROM lists WHERE list_id = $1"#,
+        list_id
+    )
+        .execute(&mut *transaction)
+        .await;
+    match result {
+        Ok(_) => {}
+        // An issue started using the list in the meantime.
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            FlashMessage::error("The list is still used by an issue that has not been sent yet.").send();
+            return Ok(see_other("/admin/lists"));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The list has been deleted.").send();
+    Ok(see_other("/admin/lists"))
+}
+
+pub const UNKNOWN_LIST: &str = "One of the lists does not exist anymore.";
+
+/// Reads the `list_ids` checkboxes of an issue form.
+pub fn parse_list_ids(list_ids:&[String]) -> Result<Vec<Uuid>,uuid::Error> {
+    list_ids.iter().map(|id| Uuid::parse_str(id.trim())).collect()
+}
+
+/// Replaces the lists an issue goes to. Returns `false`, having saved
+/// nothing, if one of the lists does not exist: dropping it could leave the
+/// issue with no list, and hence going to every confirmed subscriber.
+#[tracing::instrument(skip(connection))]
+pub async fn save_issue_lists(
+    connection:&mut PgConnection,
+    issue_id:Uuid,
+    list_ids:&[Uuid]
+) -> Result<bool,sqlx::Error> {
+    let mut distinct_ids = list_ids.to_vec();
+    distinct_ids.sort();
+    distinct_ids.dedup();
+    // Shared locks keep the lists from being deleted until the issue is saved.
+    let n_existing = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE list_id = ANY($1) FOR SHARE"#,
+        &distinct_ids
+    )
+        .fetch_all(&mut *connection)
+        .await?
+        .len();
+    if n_existing != distinct_ids.len() {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issue_lists WHERE newsletter_issues_id = $1"#,
+        issue_id
+    )
+        .execute(&mut *connection)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issues_id, list_id)
+        SELECT $1, list_id FROM lists WHERE list_id = ANY($2)
+        "#,
+        issue_id,
+        &distinct_ids
+    )
+        .execute(connection)
+        .await?;
+    Ok(true)
+}
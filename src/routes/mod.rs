@@ -1,6 +1,7 @@
 mod health_check;
 mod subscription; 
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 mod home;
 mod login;
 mod admin;
@@ -8,10 +9,29 @@ mod reset;
 mod utils;
 mod logout;
 mod newsletter;
+mod deliveries;
+mod drafts;
+mod templates;
+mod lists;
+mod tags;
+mod segments;
+mod issues;
+mod archive;
+mod webhooks;
 
 pub use subscription::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
 pub use newsletter::*;
+pub use deliveries::*;
+pub use drafts::*;
+pub use templates::*;
+pub use lists::*;
+pub use tags::*;
+pub use segments::*;
+pub use issues::*;
+pub use archive::*;
+pub use webhooks::*;
 pub use home::*;
 pub use login::*;
 pub use admin::*;
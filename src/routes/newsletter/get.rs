@@ -1,13 +1,17 @@
-use actix_web::{cookie::Cookie, http::header::ContentType, HttpResponse};
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use uuid::Uuid;
 use std::fmt::Write;
 
+use crate::routes::{e500, email_template_options, list_checkboxes, segment_options};
+
 
 
 pub async fn publish_form(
-flash_message:IncomingFlashMessages
-) -> HttpResponse {
+flash_message:IncomingFlashMessages,
+pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
 
     let mut messages = String::new();
 
@@ -16,6 +20,9 @@ flash_message:IncomingFlashMessages
     }
 
     let idempotency_key = Uuid::new_v4().to_string();
+    let template_options = email_template_options(&pool, None).await.map_err(e500)?;
+    let list_checkboxes = list_checkboxes(&pool, &[]).await.map_err(e500)?;
+    let segment_options = segment_options(&pool, None).await.map_err(e500)?;
 
     let mut response = HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -37,21 +44,58 @@ flash_message:IncomingFlashMessages
                 name="title"
                 >
                 </label>
+                <label> Content markdown
+                <textarea
+                rows="12"
+                cols="80"
+                placeholder="Write the issue in Markdown, or fill in both parts below."
+                name="markdown_content"
+                ></textarea>
+                </label>
+                <p>The HTML and text parts are rendered from the Markdown unless you fill them in yourself.</p>
+                <p>Merge tags are filled in for every subscriber: {{{{ subscriber.name }}}}, {{{{ subscriber.email }}}},
+                {{{{ unsubscribe_url }}}} and {{{{ issue.title }}}}. Give one a fallback with {{{{ subscriber.name | default: "friend" }}}}.</p>
                 <label> Content text
-                <input
-                type="text"
+                <textarea
+                rows="12"
+                cols="80"
                 placeholder="Enter newsletter Content!"
                 name="text_content"
-                >
+                ></textarea>
                 </label>
                 <label> Content html
-                <input
-                type="text"
+                <textarea
+                rows="12"
+                cols="80"
                 placeholder="Enter newsletter Content!"
                 name="html_content"
+                ></textarea>
+                </label>
+                <button type="submit" formaction="/admin/newsletter/preview" formtarget="_blank"> Preview </button>
+                <label> Template
+                <select name="email_template_id">{template_options}</select>
+                </label>
+                {list_checkboxes}
+                <label> Segment
+                <select name="segment_id">{segment_options}</select>
+                </label>
+                <p>Preview the issue to see how many subscribers it would go to.</p>
+                <label> Send at (UTC, leave empty to send now)
+                <input
+                type="datetime-local"
+                name="scheduled_for"
                 >
                 </label>
                 <button type="submit"> Post Newsletter </button>
+                <button type="submit" formaction="/admin/drafts"> Save as draft </button>
+                <label> Test recipients
+                <input
+                type="text"
+                placeholder="you@example.com, colleague@example.com"
+                name="test_recipients"
+                >
+                </label>
+                <button type="submit" formaction="/admin/newsletter/test"> Send test </button>
                 </form>
                 </body></html>
                 "#,
@@ -60,7 +104,7 @@ flash_message:IncomingFlashMessages
             response
             .add_removal_cookie(&Cookie::new("_flash", ""))
             .unwrap();
-    response
+    Ok(response)
 
 
 
@@ -1,6 +1,9 @@
-
 mod post;
 mod get;
+mod preview;
+mod send_test;
 
 pub use post::*;
 pub use get::*;
+pub use preview::*;
+pub use send_test::*;
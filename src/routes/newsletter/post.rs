@@ -1,10 +1,13 @@
 use std::fmt;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use actix_web_flash_messages::{FlashMessage, Level};
-use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use sqlx::{Connection, PgConnection, PgPool, Postgres, Transaction};
 use actix_web::{http::{header::{HeaderMap, HeaderValue},  StatusCode}, web::{self, Form}, HttpRequest, HttpResponse, ResponseError};
+use actix_web_lab::extract::UrlEncodedForm;
 use uuid::Uuid;
 
-use crate::{authentication::{get_username_from_uuid, validate_users_table, AuthError, Credentials}, domain::SubscriberEmail, email_client::EmailClient, idempotency::{save_response, try_processing, IdempotencyKey, NextAction}, middleware::UserID, routes::{e400, e500, error_chain_fmt, see_other}};
+use crate::{domain::{merge_tag_problems, EmailLayout}, authentication::{get_username_from_uuid, validate_users_table, AuthError, Credentials}, idempotency::{save_response, try_processing, IdempotencyKey, NextAction}, issue_delivery_work::enqueue_newsletter_issue, markdown::render_issue_parts, middleware::UserID, routes::{e400, e500, error_chain_fmt, parse_list_ids, parse_segment_id, parse_template_id, lock_segment, lock_template, save_issue_lists, see_other, UNKNOWN_LIST, UNKNOWN_SEGMENT, UNKNOWN_TEMPLATE}};
 
 
 #[derive(serde::Deserialize)]
@@ -12,12 +15,21 @@ pub struct FormData {
     title:String,
     html_content:String,
     text_content:String,
-    idempotency_key:String
-}
-
-
-struct ConfirmedSubscriber {
-    email:SubscriberEmail
+    /// Renders whichever of the HTML and text parts are left empty.
+    #[serde(default)]
+    markdown_content:String,
+    #[serde(default)]
+    email_template_id:String,
+    /// Left empty to send the issue to every confirmed subscriber.
+    #[serde(default)]
+    list_ids:Vec<String>,
+    /// Left empty to send the issue to everyone on its lists.
+    #[serde(default)]
+    segment_id:String,
+    idempotency_key:String,
+    /// Left empty to send the issue straight away.
+    #[serde(default)]
+    scheduled_for:String,
 }
 
 #[derive(thiserror::Error)]
@@ -55,18 +67,24 @@ impl ResponseError for PublishError {
 
 #[tracing::instrument(
     "Publishing newsletter to confirmed subscriber!",
-    skip(form,pool,email_client,user_id),
+    skip(form,pool,user_id),
     fields(username=tracing::field::Empty,user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter( 
-    form:web::Form<FormData>,
+    form:UrlEncodedForm<FormData>,
     pool:web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     user_id: web::ReqData<UserID>,
 ) -> Result<HttpResponse,actix_web::Error> {
 
-    let FormData {title,html_content,text_content,idempotency_key} = form.0;
+    let FormData {title,html_content,text_content,markdown_content,email_template_id,list_ids,segment_id,idempotency_key,scheduled_for} = form.0;
     let idempotency_key:IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let email_template_id = parse_template_id(&email_template_id).map_err(e400)?;
+    let list_ids = parse_list_ids(&list_ids).map_err(e400)?;
+    let segment_id = parse_segment_id(&segment_id).map_err(e400)?;
+    let scheduled_for = match scheduled_for.trim() {
+        "" => None,
+        s => Some(parse_send_time(s).map_err(e400)?),
+    };
 
     let user_id = user_id.into_inner();
     let username = get_username_from_uuid(&pool, *user_id).await.map_err(|e| e500(e.to_string()))?;
@@ -85,37 +103,26 @@ pub async fn publish_newsletter(
         tracing::field::display(&username)
     );
 
-    let issue_id = insert_newsletter_issue(&mut *transaction, title.as_ref(), text_content.as_ref(), html_content.as_ref())
+    if !lock_segment(&mut transaction, segment_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_SEGMENT));
+    }
+    if !lock_template(&mut transaction, email_template_id).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_TEMPLATE));
+    }
+    let issue_id = insert_newsletter_issue(&mut *transaction, title.as_ref(), text_content.as_ref(), html_content.as_ref(), markdown_content.as_ref(), email_template_id, segment_id)
         .await
         .map_err(e500)?;
-
-    enqueue_newsletter_issue(&mut *transaction, issue_id)
+    if !save_issue_lists(&mut transaction, issue_id, &list_ids).await.map_err(e500)? {
+        return Err(e400(UNKNOWN_LIST));
+    }
+    let outcome = publish_issue(&mut *transaction, issue_id, scheduled_for)
         .await
-        .map_err(e500)?;
-
+        .map_err(e500)?
+        .ok_or_else(|| e500("A freshly inserted issue was not a draft."))?;
 
     tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
-    let subscribers = get_confirmed_subscriber(&pool).await.map_err(e500)?;
-    for subscriber in &subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client.send_email(
-                    &subscriber.email, 
-                    &title, 
-                    &html_content,
-                    &text_content)
-                    .await
-                    .map_err(e500)?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                )
-            }
-        }
-    }
-    FlashMessage::success(format!("Succesfully sent email to {} subscribers",subscribers.len())).send();
+    outcome.flash_message(issue_id).send();
     let response = see_other("/admin/newsletter");
     let response = save_response(transaction, *user_id, response,&idempotency_key)
         .await
@@ -123,44 +130,38 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
-#[tracing::instrument(
-    name = "Get all confirmed subscriber",
-    skip(pool)
-)]
-async fn get_confirmed_subscriber(
-    pool:&PgPool
-) -> Result<Vec<Result<ConfirmedSubscriber,anyhow::Error>>,anyhow::Error> {
-    let row = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-        .fetch_all(pool)
-        .await?
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber{email}),
-            Err(error) => Err(anyhow::anyhow!(error))
-        }
-        )
-        .collect();
-    Ok(row)
-}
-
-
-
 fn succed_message() -> FlashMessage {
     FlashMessage::warning("The letter has beend published!")
 }
 
+/// Parses the send time of a scheduled issue. Both RFC 3339 timestamps and
+/// the `YYYY-MM-DDTHH:MM` values of a `datetime-local` input, read as UTC,
+/// are accepted. The time must be in the future.
+pub fn parse_send_time(s:&str) -> Result<DateTime<Utc>,anyhow::Error> {
+    let send_time = match DateTime::parse_from_rfc3339(s) {
+        Ok(t) => t.with_timezone(&Utc),
+        Err(_) => NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+            .with_context(|| format!("{} is not a valid send time.", s))?
+            .and_utc(),
+    };
+    if send_time <= Utc::now() {
+        anyhow::bail!("The send time must be in the future.");
+    }
+    Ok(send_time)
+}
+
+/// Inserts the issue as a draft. Use [`publish_issue`] to send it.
+/// Until then the HTML and text parts only hold what the admin wrote by hand.
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction:&mut PgConnection,
     title:&str,
     text_content:&str,
     html_content:&str,
+    markdown_content:&str,
+    email_template_id:Option<Uuid>,
+    segment_id:Option<Uuid>,
 ) -> Result<Uuid,sqlx::Error>{
     let uuid = Uuid::new_v4();
     let _sqlx = sqlx::query!(
@@ -170,39 +171,243 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            markdown_content,
+            email_template_id,
+            segment_id,
+            delivery_state
         )
-        VALUES ($1,$2,$3,$4,now())
+        VALUES ($1,$2,$3,$4,$5,$6,$7,'draft')
         "#,
         uuid,
         title,
         text_content,
         html_content,
+        markdown_content,
+        email_template_id,
+        segment_id,
     )
         .execute(transaction)
         .await?;
     Ok(uuid)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_newsletter_issue(
-    transaction: &mut PgConnection,
-    newsletter_issue_id:Uuid
-) -> Result<(),sqlx::Error> {
-    let _sqlx = sqlx::query!(
+pub enum PublishOutcome {
+    Enqueued(u64),
+    Scheduled(DateTime<Utc>),
+    /// The issue uses merge tags that cannot be rendered. It stays a draft.
+    Rejected(Vec<String>),
+}
+
+impl PublishOutcome {
+    pub fn flash_message(&self, issue_id:Uuid) -> FlashMessage {
+        match self {
+            PublishOutcome::Enqueued(n_enqueued) => FlashMessage::success(format!(
+                "The newsletter issue has been published! {} deliveries have been enqueued. \
+                <a href=\"/admin/issues/{}\">Follow its delivery</a>.",
+                n_enqueued,
+                issue_id
+            )),
+            PublishOutcome::Scheduled(scheduled_for) => FlashMessage::success(format!(
+                "The newsletter issue has been scheduled for {}. \
+                <a href=\"/admin/issues/scheduled\">See scheduled issues</a>.",
+                scheduled_for.format("%Y-%m-%d %H:%M UTC")
+            )),
+            PublishOutcome::Rejected(problems) => FlashMessage::error(format!(
+                "The newsletter issue has been kept as a draft: {} \
+                <a href=\"/admin/drafts/{}\">Edit the draft</a>.",
+                htmlescape::encode_minimal(&problems.join(" ")),
+                issue_id
+            )),
+        }
+    }
+}
+
+/// Renders the parts of a draft left empty from its Markdown source, then
+/// moves it to `sending` and enqueues it, or to `scheduled` when a send time
+/// is given. This is also when the issue gets the slug of its archive page.
+/// Issues sent to a list or a segment stay out of the archive until shown.
+/// A draft whose merge tags, or those of its layout, cannot be rendered is
+/// left untouched. Returns `None` if the issue is not a draft.
+#[tracing::instrument(skip(transaction))]
+pub async fn publish_issue(
+    transaction:&mut PgConnection,
+    issue_id:Uuid,
+    scheduled_for:Option<DateTime<Utc>>,
+) -> Result<Option<PublishOutcome>,sqlx::Error> {
+    let draft = sqlx::query!(
         r#"
-        INSERT INTO issues_delivery_queue (
-            newsletter_issues_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT
+            i.title,
+            i.markdown_content,
+            i.html_content,
+            i.text_content,
+            i.email_template_id,
+            t.html_layout AS "html_layout?",
+            t.text_layout AS "text_layout?"
+        FROM newsletter_issues i
+        LEFT JOIN email_templates t USING (email_template_id)
+        WHERE i.newsletter_issues_id = $1 AND i.delivery_state = 'draft'
+        FOR UPDATE OF i
         "#,
-        newsletter_issue_id
+        issue_id
     )
-        .execute(transaction)
+        .fetch_optional(&mut *transaction)
+        .await?;
+    let Some(draft) = draft else {
+        return Ok(None);
+    };
+    // Held until the issue is published, so that its layout stays. The
+    // foreign key already guarantees the template exists.
+    lock_template(&mut *transaction, draft.email_template_id).await?;
+    let (html_content, text_content) = render_issue_parts(
+        &draft.markdown_content,
+        &draft.html_content,
+        &draft.text_content
+    );
+    let mut problems = merge_tag_problems(&html_content, true);
+    problems.extend(merge_tag_problems(&text_content, false));
+    if let (Some(html_layout), Some(text_layout)) = (draft.html_layout, draft.text_layout) {
+        problems.extend(EmailLayout {html_layout, text_layout}.merge_tag_problems());
+    }
+    if !problems.is_empty() {
+        return Ok(Some(PublishOutcome::Rejected(problems)));
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            html_content = $3,
+            text_content = $4,
+            hidden_from_archive = segment_id IS NOT NULL OR EXISTS (
+                SELECT 1 FROM newsletter_issue_lists WHERE newsletter_issues_id = $1
+            ),
+            published_at = CASE WHEN $2::TEXT IS NULL THEN now() END,
+            scheduled_for = $2::TEXT::timestamptz,
+            delivery_state = CASE WHEN $2::TEXT IS NULL THEN 'sending' ELSE 'scheduled' END
+        WHERE newsletter_issues_id = $1
+        "#,
+        issue_id,
+        scheduled_for.map(|t| t.to_rfc3339()),
+        html_content,
+        text_content,
+    )
+        .execute(&mut *transaction)
         .await?;
+    assign_slug(&mut *transaction, issue_id, &draft.title).await?;
+    match scheduled_for {
+        Some(scheduled_for) => Ok(Some(PublishOutcome::Scheduled(scheduled_for))),
+        None => {
+            let n_enqueued = enqueue_newsletter_issue(transaction, issue_id).await?;
+            Ok(Some(PublishOutcome::Enqueued(n_enqueued)))
+        }
+    }
+}
+
+/// Turns a title into the last segment of an archive URL: lowercase ASCII
+/// letters and digits, with a dash for anything else. Other scripts and
+/// accented letters are transliterated first.
+///
+/// Issues published before the archive got their slug from the migration
+/// that added it, in SQL, which drops other letters instead: `Café` became
+/// `caf`, not `cafe`. Those slugs are kept, so that links to them still work.
+pub fn slugify(title:&str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in deunicode::deunicode(title).chars().map(|c| c.to_ascii_lowercase()) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    match slug {
+        "" => "issue".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// Gives the issue the slug of `title`, appending `-2`, `-3`, … until no
+/// other issue uses it. An issue published concurrently can still take the
+/// same slug first: the update then fails on the unique index, and is tried
+/// again with the next suffix within a savepoint.
+#[tracing::instrument(skip(connection))]
+async fn assign_slug(
+    connection:&mut PgConnection,
+    issue_id:Uuid,
+    title:&str
+) -> Result<(),sqlx::Error> {
+    let base = slugify(title);
+    let taken: Vec<String> = sqlx::query!(
+        r#"
+        SELECT slug AS "slug!"
+        FROM newsletter_issues
+        WHERE slug = $1 OR slug LIKE $1 || '-%'
+        "#,
+        base
+    )
+        .fetch_all(&mut *connection)
+        .await?
+        .into_iter()
+        .map(|r| r.slug)
+        .collect();
+    let candidates = std::iter::once(base.clone()).chain((2..).map(|n| format!("{}-{}", base, n)));
+    for slug in candidates.filter(|slug| !taken.contains(slug)) {
+        let mut savepoint = connection.begin().await?;
+        let updated = sqlx::query!(
+            "UPDATE newsletter_issues SET slug = $2 WHERE newsletter_issues_id = $1",
+            issue_id,
+            slug
+        )
+            .execute(&mut *savepoint)
+            .await;
+        match updated {
+            Ok(_) => return savepoint.commit().await,
+            Err(e) if e.as_database_error().is_some_and(|e| e.is_unique_violation()) => {
+                tracing::warn!(slug = %slug, "The slug was taken concurrently, trying the next one");
+                savepoint.rollback().await?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("there are infinitely many slug candidates")
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+
+    use super::{parse_send_time, slugify};
+
+    #[test]
+    fn slugs_keep_only_lowercase_letters_and_digits() {
+        assert_eq!(slugify("Rust 2026: What's new?"), "rust-2026-what-s-new");
+        assert_eq!(slugify("  Été -- à Paris  "), "ete-a-paris");
+        assert_eq!(slugify("Ölfeld Straße"), "olfeld-strasse");
+        assert_eq!(slugify("!!!"), "issue");
+    }
+
+    #[test]
+    fn datetime_local_values_are_read_as_utc() {
+        let next_year = Utc::now() + Duration::days(366);
+        let input = next_year.format("%Y-%m-%dT%H:%M").to_string();
+        let send_time = parse_send_time(&input).unwrap();
+        assert_eq!(send_time.format("%Y-%m-%dT%H:%M").to_string(),input);
+    }
+
+    #[test]
+    fn rfc3339_timestamps_are_accepted() {
+        let input = (Utc::now() + Duration::hours(1)).to_rfc3339();
+        assert_ok!(parse_send_time(&input));
+    }
+
+    #[test]
+    fn past_times_are_rejected() {
+        assert_err!(parse_send_time("2020-01-01T09:00"));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(parse_send_time("tomorrow morning"));
+    }
 }
@@ -0,0 +1,276 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_lab::extract::UrlEncodedForm;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{domain::merge_tag_problems, issue_delivery_work::{count_recipients, NewsletterIssue}, markdown::render_issue_parts, routes::{e400, e500, get_email_layout, parse_list_ids, parse_segment_id, parse_template_id, unsubscribe_link}, startup::{ApplicationBaseUrl, HmacSecret}};
+
+
+/// The subscriber every preview is rendered for.
+pub const SAMPLE_SUBSCRIBER_EMAIL: &str = "subscriber@example.com";
+pub const SAMPLE_SUBSCRIBER_NAME: &str = "Sample Subscriber";
+
+/// Elements that never have a closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img",
+    "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose end tag HTML lets you leave out.
+const OPTIONAL_END_TAGS: [&str; 19] = [
+    "html", "head", "body", "p", "li", "dt", "dd", "rt", "rp", "optgroup",
+    "option", "colgroup", "caption", "thead", "tbody", "tfoot", "tr", "td", "th",
+];
+
+/// The start tags that end an open `<p>`.
+const ENDS_PARAGRAPH: [&str; 32] = [
+    "address", "article", "aside", "blockquote", "details", "dialog", "div", "dl",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4",
+    "h5", "h6", "header", "hgroup", "hr", "main", "menu", "nav", "ol", "p", "pre",
+    "search", "section", "table", "ul",
+];
+
+/// Whether the start tag `opening` implicitly ends the element `open`, as
+/// `<li>` ends the previous `<li>`.
+fn ends_element(opening:&str, open:&str) -> bool {
+    match open {
+        "p" => ENDS_PARAGRAPH.contains(&opening),
+        "li" => opening == "li",
+        "dt" | "dd" => matches!(opening, "dt" | "dd"),
+        "rt" | "rp" => matches!(opening, "rt" | "rp"),
+        "option" => matches!(opening, "option" | "optgroup"),
+        "optgroup" => opening == "optgroup",
+        "colgroup" | "caption" => matches!(opening, "thead" | "tbody" | "tfoot" | "tr"),
+        "thead" | "tbody" => matches!(opening, "tbody" | "tfoot"),
+        "tr" => matches!(opening, "tr" | "tbody" | "tfoot"),
+        "td" | "th" => matches!(opening, "td" | "th" | "tr" | "tbody" | "tfoot"),
+        _ => false,
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewFormData {
+    title:String,
+    html_content:String,
+    text_content:String,
+    #[serde(default)]
+    markdown_content:String,
+    #[serde(default)]
+    email_template_id:String,
+    #[serde(default)]
+    segment_id:String,
+    #[serde(default)]
+    list_ids:Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Preview an issue",
+    skip(form,pool,base_url,hmac_secret)
+)]
+pub async fn preview_newsletter(
+    form:UrlEncodedForm<PreviewFormData>,
+    pool:web::Data<PgPool>,
+    base_url:web::Data<ApplicationBaseUrl>,
+    hmac_secret:web::Data<HmacSecret>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let PreviewFormData {title,html_content,text_content,markdown_content,email_template_id,segment_id,list_ids} = form.0;
+    let layout = match parse_template_id(&email_template_id).map_err(e400)? {
+        Some(template_id) => get_email_layout(&pool, template_id).await.map_err(e500)?,
+        None => None,
+    };
+    let segment_id = parse_segment_id(&segment_id).map_err(e400)?;
+    let list_ids = parse_list_ids(&list_ids).map_err(e400)?;
+    let n_recipients = count_recipients(&mut *pool.acquire().await.map_err(e500)?, segment_id, &list_ids)
+        .await
+        .map_err(e500)?;
+    let (html_content, text_content) = render_issue_parts(&markdown_content, &html_content, &text_content);
+
+    let mut problems = Vec::new();
+    if title.trim().is_empty() {
+        problems.push("The title is empty.".to_string());
+    }
+    if html_content.trim().is_empty() {
+        problems.push("The HTML part is empty.".to_string());
+    }
+    if text_content.trim().is_empty() {
+        problems.push("The plain-text part is empty.".to_string());
+    }
+    problems.extend(html_problems(&html_content));
+    problems.extend(merge_tag_problems(&html_content, true));
+    problems.extend(merge_tag_problems(&text_content, false));
+    if let Some(layout) = &layout {
+        problems.extend(layout.merge_tag_problems());
+    }
+
+    let issue = NewsletterIssue {title, text_content, html_content, layout};
+    let unsubscribe_url = unsubscribe_link(&base_url.0, SAMPLE_SUBSCRIBER_EMAIL, &hmac_secret);
+    let (html_content, text_content) = issue.render(
+        SAMPLE_SUBSCRIBER_EMAIL,
+        Some(SAMPLE_SUBSCRIBER_NAME),
+        &unsubscribe_url
+    );
+
+    let problems_html = if problems.is_empty() {
+        "<p>No problem found.</p>".to_string()
+    } else {
+        let mut items = String::new();
+        for problem in &problems {
+            writeln!(items, "<li>{}</li>", htmlescape::encode_minimal(problem)).unwrap();
+        }
+        format!("<ul id=\"problems\">\n{}</ul>", items)
+    };
+    let title = htmlescape::encode_minimal(&issue.title);
+    let html_content = htmlescape::encode_attribute(&html_content);
+    let text_content = htmlescape::encode_minimal(&text_content);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Preview of {title}</title>
+                </head>
+                <body>
+                <h1>Preview</h1>
+                <p>Subject: {title}</p>
+                <p>Rendered for a sample subscriber, {SAMPLE_SUBSCRIBER_NAME} &lt;{SAMPLE_SUBSCRIBER_EMAIL}&gt;.</p>
+                <p>Sent now, this issue would go to {n_recipients} subscribers.</p>
+                <h2>Problems</h2>
+                {problems_html}
+                <div style="display: flex; gap: 1em;">
+                <section style="flex: 1;">
+                <h2>HTML</h2>
+                <iframe sandbox style="width: 100%; height: 600px;" srcdoc="{html_content}"></iframe>
+                </section>
+                <section style="flex: 1;">
+                <h2>Plain text</h2>
+                <pre style="white-space: pre-wrap;">{text_content}</pre>
+                </section>
+                </div>
+                </body>
+                </html>"#
+        )))
+}
+
+/// Finds unclosed, unopened and unterminated tags. This is a balance check
+/// rather than a full HTML parser: it is meant to catch copy-paste accidents.
+/// End tags HTML lets you leave out, like those of `<li>` or `<p>`, are not
+/// reported.
+pub fn html_problems(html:&str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut open: Vec<String> = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            match comment.find("-->") {
+                Some(end) => rest = &comment[end + 3..],
+                None => {
+                    problems.push("An HTML comment is never closed.".to_string());
+                    return problems;
+                }
+            }
+            continue;
+        }
+        // `a < b` is text, not a tag.
+        if !rest[1..].starts_with(|c:char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) {
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            problems.push("A tag is never closed with `>`.".to_string());
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_lowercase();
+            match open.iter().rposition(|t| *t == name) {
+                Some(i) => {
+                    for unclosed in open.drain(i..).skip(1) {
+                        if !OPTIONAL_END_TAGS.contains(&unclosed.as_str()) {
+                            problems.push(format!("<{}> is never closed.", unclosed));
+                        }
+                    }
+                }
+                None => problems.push(format!("</{}> has no matching opening tag.", name)),
+            }
+            continue;
+        }
+
+        let name: String = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect::<String>()
+            .to_lowercase();
+        while open.last().is_some_and(|open| ends_element(&name, open)) {
+            open.pop();
+        }
+        if name == "script" || name == "style" {
+            match rest.to_ascii_lowercase().find(&format!("</{}", name)) {
+                Some(close) => {
+                    rest = &rest[close..];
+                    open.push(name);
+                }
+                None => problems.push(format!("<{}> is never closed.", name)),
+            }
+            continue;
+        }
+        if !tag.ends_with('/') && !VOID_ELEMENTS.contains(&name.as_str()) {
+            open.push(name);
+        }
+    }
+
+    for unclosed in open {
+        if !OPTIONAL_END_TAGS.contains(&unclosed.as_str()) {
+            problems.push(format!("<{}> is never closed.", unclosed));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_problems;
+
+    #[test]
+    fn balanced_html_has_no_problem() {
+        let html = r#"<!DOCTYPE html><!-- a <b> comment --><div class="x"><p>1 < 2 <br><img src="a.png"/></p>
+            <style>p > a { color: red; }</style></div>"#;
+        assert!(html_problems(html).is_empty());
+    }
+
+    #[test]
+    fn unclosed_tags_are_reported() {
+        assert_eq!(html_problems("<div><b>Hello</div>"),vec!["<b> is never closed."]);
+        assert_eq!(html_problems("<div><b>Hello</b>"),vec!["<div> is never closed."]);
+    }
+
+    #[test]
+    fn optional_end_tags_may_be_left_out() {
+        assert!(html_problems("<ul><li>a<li>b</ul>").is_empty());
+        assert!(html_problems("<div><p>One<p>Two<ul><li>Three</ul></div>").is_empty());
+        assert!(html_problems("<table><tr><td>1<td>2<tr><td>3</table>").is_empty());
+        assert!(html_problems("<dl><dt>Term<dd>Definition</dl><p>Last").is_empty());
+        assert_eq!(html_problems("<ul><li><b>a<li>b</ul>"),vec!["<b> is never closed."]);
+    }
+
+    #[test]
+    fn stray_closing_tags_are_reported() {
+        assert_eq!(html_problems("<p>Hello</p></div>"),vec!["</div> has no matching opening tag."]);
+        assert_eq!(html_problems("<p>1 <2</p></p>"),vec!["</p> has no matching opening tag."]);
+    }
+
+    #[test]
+    fn unterminated_tags_are_reported() {
+        assert_eq!(html_problems("<p>Hello</p><a href=\"x\""),vec!["A tag is never closed with `>`."]);
+        assert_eq!(html_problems("<p>Hello</p><!-- oops"),vec!["An HTML comment is never closed."]);
+    }
+}
@@ -0,0 +1,104 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+use crate::{domain::SubscriberEmail, email_client::EmailClient, issue_delivery_work::{unsubscribe_headers, NewsletterIssue}, markdown::render_issue_parts, routes::{e400, e500, get_email_layout, parse_template_id, see_other, unsubscribe_link}, startup::{ApplicationBaseUrl, HmacSecret}};
+use sqlx::PgPool;
+
+
+pub const TEST_SUBJECT_PREFIX: &str = "[TEST] ";
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    title:String,
+    html_content:String,
+    text_content:String,
+    #[serde(default)]
+    markdown_content:String,
+    #[serde(default)]
+    email_template_id:String,
+    /// Comma or whitespace separated addresses.
+    test_recipients:String,
+}
+
+/// Sends the issue as it stands in the form to a few addresses, rendered as
+/// subscribers get it. Nothing is stored and nothing goes through the
+/// delivery queue.
+#[tracing::instrument(
+    name = "Send a test email of an issue",
+    skip(form,pool,email_client,base_url,hmac_secret),
+    fields(test_recipients = %form.test_recipients)
+)]
+pub async fn send_test_email(
+    form:web::Form<TestEmailFormData>,
+    pool:web::Data<PgPool>,
+    email_client:web::Data<EmailClient>,
+    base_url:web::Data<ApplicationBaseUrl>,
+    hmac_secret:web::Data<HmacSecret>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let TestEmailFormData {title,html_content,text_content,markdown_content,email_template_id,test_recipients} = form.0;
+    let (html_content, text_content) = render_issue_parts(&markdown_content, &html_content, &text_content);
+    let layout = match parse_template_id(&email_template_id).map_err(e400)? {
+        Some(template_id) => get_email_layout(&pool, template_id).await.map_err(e500)?,
+        None => None,
+    };
+    let issue = NewsletterIssue {
+        title,
+        html_content,
+        text_content,
+        layout,
+    };
+
+    let recipients: Result<Vec<_>,_> = test_recipients
+        .split(|c:char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| SubscriberEmail::parse(s.to_string()))
+        .collect();
+    let recipients = match recipients {
+        Ok(recipients) if !recipients.is_empty() => recipients,
+        Ok(_) => {
+            FlashMessage::error("Enter at least one address to send the test email to.").send();
+            return Ok(see_other("/admin/newsletter"));
+        }
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/newsletter"));
+        }
+    };
+
+    let subject = format!("{}{}", TEST_SUBJECT_PREFIX, issue.title);
+    let mut sent = Vec::with_capacity(recipients.len());
+    let mut failed = Vec::new();
+    for recipient in &recipients {
+        // Test recipients are not subscribers: the name falls back to its default.
+        let unsubscribe_url = unsubscribe_link(&base_url.0, recipient.as_ref(), &hmac_secret);
+        let (html_content, text_content) = issue.render(recipient.as_ref(), None, &unsubscribe_url);
+        let list_unsubscribe = format!("<{}>", unsubscribe_url);
+        let headers = unsubscribe_headers(&list_unsubscribe);
+        match email_client.send_email_with_headers(recipient, &subject, &html_content, &text_content, &headers).await {
+            Ok(_) => sent.push(recipient.as_ref()),
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    recipient = %recipient,
+                    "Failed to send a test email"
+                );
+                failed.push(recipient.as_ref());
+            }
+        }
+    }
+
+    if !sent.is_empty() {
+        FlashMessage::info(format!(
+            "A test email has been sent to {}.",
+            htmlescape::encode_minimal(&sent.join(", "))
+        )).send();
+    }
+    if !failed.is_empty() {
+        FlashMessage::error(format!(
+            "Failed to send the test email to {}.",
+            htmlescape::encode_minimal(&failed.join(", "))
+        )).send();
+    }
+    Ok(see_other("/admin/newsletter"))
+}
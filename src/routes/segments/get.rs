@@ -0,0 +1,173 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::{issue_delivery_work::count_recipients, routes::{e500, tag_options}};
+
+
+struct SegmentSummary {
+    segment_id:Uuid,
+    name:String,
+    tag_name:Option<String>,
+    signed_up_from:Option<String>,
+    signed_up_until:Option<String>,
+    signup_source:Option<String>,
+}
+
+impl SegmentSummary {
+    fn conditions(&self) -> String {
+        let mut conditions = Vec::new();
+        if let Some(tag_name) = &self.tag_name {
+            conditions.push(format!("tagged {}", tag_name));
+        }
+        match (&self.signed_up_from, &self.signed_up_until) {
+            (Some(from), Some(until)) => conditions.push(format!("signed up from {} to {}", from, until)),
+            (Some(from), None) => conditions.push(format!("signed up on or after {}", from)),
+            (None, Some(until)) => conditions.push(format!("signed up on or before {}", until)),
+            (None, None) => {}
+        }
+        if let Some(signup_source) = &self.signup_source {
+            conditions.push(format!("signed up through {}", signup_source));
+        }
+        conditions.join(", ")
+    }
+}
+
+#[tracing::instrument(
+    name = "Show the segments",
+    skip(pool,flash_message)
+)]
+pub async fn segments_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let segments = get_segments(&pool).await.map_err(e500)?;
+    let mut connection = pool.acquire().await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for segment in &segments {
+        let id = segment.segment_id;
+        let n_recipients = count_recipients(&mut connection, Some(id), &[]).await.map_err(e500)?;
+        writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>
+            <form action="/admin/segments/{id}/delete" method="post">
+            <button type="submit">Delete</button>
+            </form>
+            </td></tr>"#,
+            htmlescape::encode_minimal(&segment.name),
+            htmlescape::encode_minimal(&segment.conditions()),
+            n_recipients,
+        ).unwrap();
+    }
+    let segments_html = if segments.is_empty() {
+        "<p>There are no segments.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Name</th><th>Conditions</th><th>Recipients</th><th></th></tr>\n{}</table>",
+            rows
+        )
+    };
+    let tag_options = tag_options(&pool, None).await.map_err(e500)?;
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Segments</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Segments</h1>
+                {segments_html}
+                <h2>New segment</h2>
+                <p>Leave a condition empty to ignore it. Signup dates are UTC days, both included.</p>
+                <form action="/admin/segments" method="post">
+                <label> Name
+                <input type="text" name="name">
+                </label>
+                <label> Tag
+                <select name="tag_id">{tag_options}</select>
+                </label>
+                <label> Signed up from
+                <input type="date" name="signed_up_from">
+                </label>
+                <label> Signed up until
+                <input type="date" name="signed_up_until">
+                </label>
+                <label> Signup source
+                <input type="text" name="signup_source">
+                </label>
+                <button type="submit"> Create segment </button>
+                </form>
+                <p><a href="/admin/tags">Tags</a></p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+/// The `<option>`s of a `segment_id` select, with `selected` picked.
+#[tracing::instrument(
+    name = "Get segment options",
+    skip(pool)
+)]
+pub async fn segment_options(
+    pool:&PgPool,
+    selected:Option<Uuid>
+) -> Result<String,sqlx::Error> {
+    let mut options = String::from(r#"<option value="">Everyone</option>"#);
+    for segment in get_segments(pool).await? {
+        let id = segment.segment_id;
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            id,
+            if selected == Some(id) { " selected" } else { "" },
+            htmlescape::encode_minimal(&segment.name),
+        ).unwrap();
+    }
+    Ok(options)
+}
+
+#[tracing::instrument(
+    name = "Get segments",
+    skip(pool)
+)]
+async fn get_segments(
+    pool:&PgPool
+) -> Result<Vec<SegmentSummary>,sqlx::Error> {
+    sqlx::query_as!(
+        SegmentSummary,
+        r#"
+        SELECT
+            g.segment_id,
+            g.name,
+            t.name AS "tag_name?",
+            g.signed_up_from::TEXT AS signed_up_from,
+            g.signed_up_until::TEXT AS signed_up_until,
+            g.signup_source
+        FROM segments g
+        LEFT JOIN tags t USING (tag_id)
+        ORDER BY g.name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
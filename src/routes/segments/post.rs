@@ -0,0 +1,176 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::SegmentFilter, routes::{e500, see_other}};
+
+
+#[derive(serde::Deserialize)]
+pub struct SegmentFormData {
+    name:String,
+    #[serde(default)]
+    tag_id:String,
+    #[serde(default)]
+    signed_up_from:String,
+    #[serde(default)]
+    signed_up_until:String,
+    #[serde(default)]
+    signup_source:String,
+}
+
+impl SegmentFormData {
+    fn parse(self) -> Result<(String,SegmentFilter),String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("The segment needs a name.".to_string());
+        }
+        let filter = SegmentFilter::parse(
+            &self.tag_id,
+            &self.signed_up_from,
+            &self.signed_up_until,
+            &self.signup_source
+        )?;
+        Ok((name, filter))
+    }
+}
+
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(form,pool)
+)]
+pub async fn create_segment(
+    form:web::Form<SegmentFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let (name, filter) = match form.0.parse() {
+        Ok(segment) => segment,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, tag_id, signed_up_from, signed_up_until, signup_source)
+        SELECT $1, $2, $3, $4::TEXT::date, $5::TEXT::date, $6
+        WHERE $3::uuid IS NULL OR EXISTS (SELECT 1 FROM tags WHERE tag_id = $3)
+        "#,
+        Uuid::new_v4(),
+        name,
+        filter.tag_id,
+        filter.signed_up_from.map(|d| d.to_string()),
+        filter.signed_up_until.map(|d| d.to_string()),
+        filter.signup_source,
+    )
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(r) if r.rows_affected() == 0 => FlashMessage::error("The tag does not exist.").send(),
+        Ok(_) => FlashMessage::info("The segment has been created.").send(),
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_unique_violation()) => {
+            FlashMessage::error(format!(
+                "A segment named {} already exists.",
+                htmlescape::encode_minimal(&name)
+            )).send()
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+/// Segments still used by a draft or a scheduled issue are kept: without
+/// them the issue would go to its whole audience. Sent issues stop
+/// recording the segment.
+#[tracing::instrument(
+    name = "Delete a segment",
+    skip(pool)
+)]
+pub async fn delete_segment(
+    segment_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Locked, so that none of them is published while the segment goes away.
+    let n_pending = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id
+        FROM newsletter_issues
+        WHERE segment_id = $1
+        AND delivery_state IN ('draft', 'scheduled')
+        FOR UPDATE
+        "#,
+        segment_id
+    )
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .len();
+    if n_pending > 0 {
+        FlashMessage::error(format!(
+            "The segment is still used by {} issues that have not been sent yet.",
+            n_pending
+        )).send();
+        return Ok(see_other("/admin/segments"));
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET segment_id = NULL
+        WHERE segment_id = $1
+        AND delivery_state NOT IN ('draft', 'scheduled')
+        "#,
+        segment_id
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    let result = sqlx::query!(
+        r#"DELETE FROM segments WHERE segment_id = $1"#,
+        segment_id
+    )
+        .execute(&mut *transaction)
+        .await;
+    match result {
+        Ok(_) => {}
+        // An issue started using the segment in the meantime.
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            FlashMessage::error("The segment is still used by an issue that has not been sent yet.").send();
+            return Ok(see_other("/admin/segments"));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The segment has been deleted.").send();
+    Ok(see_other("/admin/segments"))
+}
+
+pub const UNKNOWN_SEGMENT: &str = "The segment does not exist anymore.";
+
+/// Whether the segment of an issue form still exists. A shared lock keeps
+/// it from being deleted until the issue is saved.
+#[tracing::instrument(skip(connection))]
+pub async fn lock_segment(
+    connection:&mut PgConnection,
+    segment_id:Option<Uuid>
+) -> Result<bool,sqlx::Error> {
+    let Some(segment_id) = segment_id else {
+        return Ok(true);
+    };
+    let segment = sqlx::query!(
+        r#"SELECT segment_id FROM segments WHERE segment_id = $1 FOR SHARE"#,
+        segment_id
+    )
+        .fetch_optional(connection)
+        .await?;
+    Ok(segment.is_some())
+}
+
+/// Reads the `segment_id` field of a form, where empty means everyone.
+pub fn parse_segment_id(s:&str) -> Result<Option<Uuid>,uuid::Error> {
+    match s.trim() {
+        "" => Ok(None),
+        s => Uuid::parse_str(s).map(Some),
+    }
+}
@@ -11,6 +11,8 @@ use crate::{domain::NewSubscriber, email_client:: EmailClient, startup::Applicat
 
 
 
+const MAX_SIGNUP_SOURCE_LENGTH: usize = 100;
+
 #[tracing::instrument(
     name="Starting subscriber function got triggered",
     skip(form,pool,email_client,base_url),
@@ -30,11 +32,43 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
+    let list_id = match form.list.trim() {
+        "" => None,
+        slug => Some(
+            get_list_id(&mut transaction, slug)
+                .await
+                .context("Failed to look up the list to join")?
+                .ok_or_else(|| SubscriberError::ValidationError(format!("{} is not a known list.", slug)))?
+        ),
+    };
+
+    let signup_source = match form.source.trim() {
+        "" => None,
+        source if source.chars().count() > MAX_SIGNUP_SOURCE_LENGTH => {
+            return Err(SubscriberError::ValidationError(format!(
+                "The signup source is longer than {} characters.",
+                MAX_SIGNUP_SOURCE_LENGTH
+            )));
+        }
+        source => Some(source.to_string()),
+    };
+
     let new_subscriber = form.0.try_into().map_err(|e| SubscriberError::ValidationError(e))?;
 
-    let subscriber_id = query_to_subscriptions(&new_subscriber, &mut *transaction)
+    let Some(subscriber_id) = query_to_subscriptions(&new_subscriber, signup_source.as_deref(), &mut transaction)
         .await
-        .context("Failed to insert new subscriber in the database")?;
+        .context("Failed to insert new subscriber in the database")? else {
+        // Answered like any other signup, so that the form does not tell
+        // which addresses bounced or complained.
+        tracing::info!("Not sending a confirmation email to an address that cannot be mailed");
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    if let Some(list_id) = list_id {
+        join_list(&mut transaction, list_id, subscriber_id)
+            .await
+            .context("Failed to add the subscriber to the list")?;
+    }
 
     let subscription_token = generate_subscriptions_token();
 
@@ -57,36 +91,112 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Someone who already subscribed keeps their row, signup source included,
+/// so that they can join another list: the confirmation link sent next
+/// confirms that list too. Returns `None` for an address that must not be
+/// mailed: a suppressed one, or a subscriber who is neither pending nor
+/// confirmed.
 #[tracing::instrument (
     name = " Saving query to subscriptions ",
     skip(new_subscriber,transaction)
 )]
 async fn query_to_subscriptions(
     new_subscriber: &NewSubscriber,
+    signup_source: Option<&str>,
     transaction: &mut PgConnection
-) -> Result<Uuid,sqlx::Error> {
+) -> Result<Option<Uuid>,sqlx::Error> {
+
+    let suppressed = sqlx::query!(
+        r#"SELECT email FROM email_suppressions WHERE email = $1"#,
+        new_subscriber.email.as_ref(),
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    if suppressed.is_some() {
+        return Ok(None);
+    }
 
     let subs_id = Uuid::new_v4();
 
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions ( id, email, name, subscribed_at,status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmations')
+        INSERT INTO subscriptions ( id, email, name, subscribed_at,status, signup_source)
+        VALUES ($1, $2, $3, $4, 'pending_confirmations', $5)
+        ON CONFLICT (email) DO NOTHING
         "#,
         subs_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         OffsetDateTime::now_utc(),
+        signup_source,
     )
-        .execute(transaction)
+        .execute(&mut *transaction)
         .await
         .map_err(|e| {
             tracing::error!("Failed to save/ query to database: {:?}",e);
             e
-        })?;
+        })?
+        .rows_affected();
+    if inserted > 0 {
+        return Ok(Some(subs_id));
+    }
+
+    let existing = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        new_subscriber.email.as_ref(),
+    )
+        .fetch_one(transaction)
+        .await?;
+    match existing.status.as_str() {
+        "pending_confirmations" | "confirmed" => Ok(Some(existing.id)),
+        _ => Ok(None),
+    }
+
+}
 
-    Ok(subs_id)
+#[tracing::instrument(
+    name = "Get the list to join",
+    skip(transaction)
+)]
+async fn get_list_id(
+    transaction: &mut PgConnection,
+    slug: &str
+) -> Result<Option<Uuid>,sqlx::Error> {
+    let list = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE slug = $1"#,
+        slug
+    )
+        .fetch_optional(transaction)
+        .await?;
+    Ok(list.map(|l| l.list_id))
+}
 
+/// A membership waits for the subscriber to confirm, unless it already was.
+#[tracing::instrument(
+    name = "Join a list",
+    skip(transaction)
+)]
+async fn join_list(
+    transaction: &mut PgConnection,
+    list_id: Uuid,
+    subscriber_id: Uuid
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        VALUES ($1, $2, 'pending_confirmations')
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = CASE
+            WHEN list_memberships.status = 'confirmed' THEN 'confirmed'
+            ELSE 'pending_confirmations'
+        END
+        "#,
+        list_id,
+        subscriber_id
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
 }
 
 #[tracing::instrument (
@@ -98,7 +208,7 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url:&str,
     subscription_token: &str
-) -> Result<(),reqwest::Error> {
+) -> Result<(),anyhow::Error> {
     let confirmation_link =
         format!("{}/subscriptions/confirm?subscription_token={}",
             base_url,
@@ -122,7 +232,8 @@ pub async fn send_confirmation_email(
         &html_body,
         &plain_body,
     )
-        .await
+        .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -74,6 +74,9 @@ pub async fn confirm(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Confirms the lists the subscriber asked to join along with the subscriber.
+/// A subscriber who bounced, complained or unsubscribed since the link was
+/// sent stays as they are.
 #[tracing::instrument(
     name = "Mark subscriber as confirmed"
 )]
@@ -81,13 +84,30 @@ async fn subscriber_confirm(
     subscriber_id: Uuid,
     pool:&PgPool
 ) -> Result<(),sqlx::Error> {
-    let _result = sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+    let mut transaction = pool.begin().await?;
+    let confirmed = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status IN ('pending_confirmations', 'confirmed')
+        "#,
         subscriber_id
     )
-        .execute(pool)
+        .execute(&mut *transaction)
         .await?;
-    Ok(())
+    if confirmed.rows_affected() == 0 {
+        return transaction.commit().await;
+    }
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmations'
+        "#,
+        subscriber_id
+    )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await
 }
 
 #[tracing::instrument(
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use actix_web::{http::{header::ContentType, StatusCode}, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{routes::error_chain_fmt, startup::HmacSecret};
+
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    email: String,
+    tag: String,
+}
+
+impl UnsubscribeParameters {
+    fn verify(self, secret:&HmacSecret) -> Result<String,anyhow::Error> {
+        let tag = hex::decode(self.tag)?;
+        unsubscribe_mac(secret, &self.email).verify_slice(&tag)?;
+        Ok(self.email)
+    }
+}
+
+fn unsubscribe_mac(secret:&HmacSecret, email:&str) -> Hmac<sha2::Sha256> {
+    let query_string = format!(
+        "email={}",
+        urlencoding::Encoded::new(email)
+    );
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(
+        secret.0.expose_secret().as_bytes()
+    ).unwrap();
+    mac.update(query_string.as_bytes());
+    mac
+}
+
+/// Builds the signed one-click unsubscribe link for `email`.
+pub fn unsubscribe_link(
+    base_url:&str,
+    email:&str,
+    secret:&HmacSecret
+) -> String {
+    let tag = unsubscribe_mac(secret, email).finalize().into_bytes();
+    format!(
+        "{}/subscriptions/unsubscribe?email={}&tag={:x}",
+        base_url,
+        urlencoding::Encoded::new(email),
+        tag
+    )
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("The unsubscribe link is not valid!")]
+    InvalidLink(#[source] anyhow::Error),
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            UnsubscribeError::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[tracing::instrument(
+    name = "Show the unsubscribe confirmation page",
+    skip(parameters,hmac_secret)
+)]
+pub async fn unsubscribe_form(
+    parameters:web::Query<UnsubscribeParameters>,
+    hmac_secret:web::Data<HmacSecret>,
+) -> Result<HttpResponse,UnsubscribeError> {
+    let tag = parameters.tag.clone();
+    let email = parameters.0
+        .verify(&hmac_secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+    let email_html = htmlescape::encode_minimal(&email);
+    let action = format!(
+        "/subscriptions/unsubscribe?email={}&amp;tag={}",
+        urlencoding::Encoded::new(&email),
+        tag
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribe</title>
+                </head>
+                <body>
+                <p>Do you want to stop receiving our newsletter at {email_html}?</p>
+                <form action="{action}" method="post">
+                <button type="submit">Unsubscribe</button>
+                </form>
+                </body>
+                </html>"#
+    )))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters,pool,hmac_secret)
+)]
+pub async fn unsubscribe(
+    parameters:web::Query<UnsubscribeParameters>,
+    pool:web::Data<PgPool>,
+    hmac_secret:web::Data<HmacSecret>,
+) -> Result<HttpResponse,UnsubscribeError> {
+    let email = parameters.0
+        .verify(&hmac_secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+
+    mark_subscriber_unsubscribed(&pool, &email)
+        .await
+        .context("Failed to mark the subscriber as unsubscribed in the database!")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Unsubscribed</title>
+            </head>
+            <body>
+            <p>You have been unsubscribed. You will not receive any further issues.</p>
+            </body>
+            </html>"#
+        ))
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(pool)
+)]
+async fn mark_subscriber_unsubscribed(
+    pool:&PgPool,
+    email:&str
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1"#,
+        email
+    )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
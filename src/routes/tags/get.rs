@@ -0,0 +1,128 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::routes::e500;
+
+
+struct TagSummary {
+    tag_id:Uuid,
+    name:String,
+    n_subscribers:i64,
+}
+
+#[tracing::instrument(
+    name = "Show the tags",
+    skip(pool,flash_message)
+)]
+pub async fn tags_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let tags = get_tags(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for tag in &tags {
+        writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td></tr>"#,
+            htmlescape::encode_minimal(&tag.name),
+            tag.n_subscribers,
+        ).unwrap();
+    }
+    let tags_html = if tags.is_empty() {
+        "<p>There are no tags.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Name</th><th>Subscribers</th></tr>\n{}</table>",
+            rows
+        )
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Tags</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Tags</h1>
+                {tags_html}
+                <form action="/admin/tags" method="post">
+                <label> Tag
+                <input type="text" name="name">
+                </label>
+                <label> Subscriber emails
+                <textarea rows="6" cols="60" name="emails" placeholder="one@example.com, two@example.com"></textarea>
+                </label>
+                <button type="submit"> Tag subscribers </button>
+                <button type="submit" formaction="/admin/tags/remove"> Remove tag </button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+/// The `<option>`s of a `tag_id` select, with `selected` picked.
+#[tracing::instrument(
+    name = "Get tag options",
+    skip(pool)
+)]
+pub async fn tag_options(
+    pool:&PgPool,
+    selected:Option<Uuid>
+) -> Result<String,sqlx::Error> {
+    let mut options = String::from(r#"<option value="">Any tag</option>"#);
+    for tag in get_tags(pool).await? {
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            tag.tag_id,
+            if selected == Some(tag.tag_id) { " selected" } else { "" },
+            htmlescape::encode_minimal(&tag.name),
+        ).unwrap();
+    }
+    Ok(options)
+}
+
+#[tracing::instrument(
+    name = "Get tags",
+    skip(pool)
+)]
+async fn get_tags(
+    pool:&PgPool
+) -> Result<Vec<TagSummary>,sqlx::Error> {
+    sqlx::query_as!(
+        TagSummary,
+        r#"
+        SELECT
+            t.tag_id,
+            t.name,
+            count(st.subscriber_id) AS "n_subscribers!"
+        FROM tags t
+        LEFT JOIN subscriber_tags st USING (tag_id)
+        GROUP BY t.tag_id
+        ORDER BY t.name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
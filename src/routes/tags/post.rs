@@ -0,0 +1,149 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::{e500, see_other};
+
+
+#[derive(serde::Deserialize)]
+pub struct TagFormData {
+    name:String,
+    /// Separated by commas or whitespace.
+    emails:String,
+}
+
+impl TagFormData {
+    fn parse(&self) -> Result<(&str,Vec<String>),&'static str> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("The tag needs a name.");
+        }
+        let emails: Vec<String> = self.emails
+            .split(|c:char| c == ',' || c.is_whitespace())
+            .filter(|email| !email.is_empty())
+            .map(str::to_string)
+            .collect();
+        if emails.is_empty() {
+            return Err("No subscriber email was given.");
+        }
+        Ok((name, emails))
+    }
+}
+
+/// Creates the tag if it is new. Emails no subscriber uses are reported.
+#[tracing::instrument(
+    name = "Tag subscribers",
+    skip(form,pool)
+)]
+pub async fn tag_subscribers(
+    form:web::Form<TagFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let (name, emails) = match form.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let tag_id = sqlx::query!(
+        r#"
+        INSERT INTO tags (tag_id, name)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+        RETURNING tag_id
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .tag_id;
+    let n_tagged = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag_id)
+        SELECT id, $1 FROM subscriptions WHERE email = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        tag_id,
+        &emails
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .rows_affected();
+    transaction.commit().await.map_err(e500)?;
+    let unknown = unknown_emails(&pool, &emails).await.map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} subscribers have been tagged {}.",
+        n_tagged,
+        htmlescape::encode_minimal(name)
+    )).send();
+    if !unknown.is_empty() {
+        FlashMessage::warning(format!(
+            "No subscriber uses {}.",
+            htmlescape::encode_minimal(&unknown.join(", "))
+        )).send();
+    }
+    Ok(see_other("/admin/tags"))
+}
+
+#[tracing::instrument(
+    name = "Untag subscribers",
+    skip(form,pool)
+)]
+pub async fn untag_subscribers(
+    form:web::Form<TagFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let (name, emails) = match form.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+    let n_untagged = sqlx::query!(
+        r#"
+        DELETE FROM subscriber_tags st
+        USING tags t, subscriptions s
+        WHERE st.tag_id = t.tag_id
+        AND st.subscriber_id = s.id
+        AND t.name = $1
+        AND s.email = ANY($2)
+        "#,
+        name,
+        &emails
+    )
+        .execute(pool.get_ref())
+        .await
+        .map_err(e500)?
+        .rows_affected();
+    FlashMessage::info(format!(
+        "The tag {} has been removed from {} subscribers.",
+        htmlescape::encode_minimal(name),
+        n_untagged
+    )).send();
+    Ok(see_other("/admin/tags"))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn unknown_emails(
+    pool:&PgPool,
+    emails:&[String]
+) -> Result<Vec<String>,sqlx::Error> {
+    let known: Vec<String> = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE email = ANY($1)"#,
+        emails
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    Ok(emails.iter().filter(|email| !known.contains(email)).cloned().collect())
+}
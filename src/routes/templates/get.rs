@@ -0,0 +1,242 @@
+use actix_web::{cookie::Cookie, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use uuid::Uuid;
+use std::fmt::Write;
+
+use crate::{domain::{EmailLayout, CONTENT_SLOT}, routes::e500};
+
+
+struct TemplateSummary {
+    email_template_id:Uuid,
+    name:String,
+    updated_at:String,
+}
+
+struct Template {
+    name:String,
+    html_layout:String,
+    text_layout:String,
+}
+
+#[tracing::instrument(
+    name = "Show the email templates",
+    skip(pool,flash_message)
+)]
+pub async fn templates_page(
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let templates = get_templates(&pool).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for template in &templates {
+        let id = template.email_template_id;
+        writeln!(
+            rows,
+            r#"<tr><td><a href="/admin/templates/{id}">{}</a></td><td>{}</td><td>
+            <form action="/admin/templates/{id}/delete" method="post">
+            <button type="submit">Delete</button>
+            </form>
+            </td></tr>"#,
+            htmlescape::encode_minimal(&template.name),
+            template.updated_at,
+        ).unwrap();
+    }
+    let templates_html = if templates.is_empty() {
+        "<p>There are no templates.</p>".to_string()
+    } else {
+        format!(
+            "<table>\n<tr><th>Name</th><th>Last saved</th><th></th></tr>\n{}</table>",
+            rows
+        )
+    };
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Email templates</title>
+                </head>
+                <body>
+                {messages}
+                <h1>Email templates</h1>
+                {templates_html}
+                <p><a href="/admin/templates/new">New template</a></p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    Ok(response)
+}
+
+pub async fn new_template_form(
+    flash_message:IncomingFlashMessages
+) -> HttpResponse {
+    let template = Template {
+        name: String::new(),
+        html_layout: format!("<html>\n<body>\n{}\n</body>\n</html>", CONTENT_SLOT),
+        text_layout: CONTENT_SLOT.to_string(),
+    };
+    template_form("/admin/templates", &template, flash_message)
+}
+
+#[tracing::instrument(
+    name = "Show the email template editor",
+    skip(pool,flash_message)
+)]
+pub async fn edit_template_form(
+    template_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+    flash_message:IncomingFlashMessages
+) -> Result<HttpResponse,actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let template = get_template(&pool, template_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown email template."))?;
+    Ok(template_form(&format!("/admin/templates/{}", template_id), &template, flash_message))
+}
+
+fn template_form(
+    action:&str,
+    template:&Template,
+    flash_message:IncomingFlashMessages
+) -> HttpResponse {
+    let mut messages = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(messages,"<p><i>{}</i></p>",m.content()).unwrap();
+    }
+
+    let name = htmlescape::encode_attribute(&template.name);
+    let html_layout = htmlescape::encode_minimal(&template.html_layout);
+    let text_layout = htmlescape::encode_minimal(&template.text_layout);
+
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Email template</title>
+                </head>
+                <body>
+                {messages}
+                <form action="{action}" method="post">
+                <label> Name
+                <input type="text" name="name" value="{name}">
+                </label>
+                <p>Both layouts need a {CONTENT_SLOT} slot, where the content of the issue goes.</p>
+                <label> HTML layout
+                <textarea rows="16" cols="80" name="html_layout">{html_layout}</textarea>
+                </label>
+                <label> Text layout
+                <textarea rows="8" cols="80" name="text_layout">{text_layout}</textarea>
+                </label>
+                <button type="submit"> Save template </button>
+                </form>
+                <p><a href="/admin/templates">&lt;- Back</a></p>
+                </body>
+                </html>"#
+        ));
+
+    response
+        .add_removal_cookie(&Cookie::new("_flash", ""))
+        .unwrap();
+    response
+}
+
+/// The `<option>`s of an `email_template_id` select, with `selected` picked.
+#[tracing::instrument(
+    name = "Get email template options",
+    skip(pool)
+)]
+pub async fn email_template_options(
+    pool:&PgPool,
+    selected:Option<Uuid>
+) -> Result<String,sqlx::Error> {
+    let mut options = String::from(r#"<option value="">No template</option>"#);
+    for template in get_templates(pool).await? {
+        let id = template.email_template_id;
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            id,
+            if selected == Some(id) { " selected" } else { "" },
+            htmlescape::encode_minimal(&template.name),
+        ).unwrap();
+    }
+    Ok(options)
+}
+
+#[tracing::instrument(
+    name = "Get an email layout",
+    skip(pool)
+)]
+pub async fn get_email_layout(
+    pool:&PgPool,
+    template_id:Uuid
+) -> Result<Option<EmailLayout>,sqlx::Error> {
+    Ok(get_template(pool, template_id).await?.map(|t| EmailLayout {
+        html_layout: t.html_layout,
+        text_layout: t.text_layout,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get email templates",
+    skip(pool)
+)]
+async fn get_templates(
+    pool:&PgPool
+) -> Result<Vec<TemplateSummary>,sqlx::Error> {
+    sqlx::query_as!(
+        TemplateSummary,
+        r#"
+        SELECT
+            email_template_id,
+            name,
+            updated_at::TEXT AS "updated_at!"
+        FROM email_templates
+        ORDER BY name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
+
+#[tracing::instrument(
+    name = "Get an email template",
+    skip(pool)
+)]
+async fn get_template(
+    pool:&PgPool,
+    template_id:Uuid
+) -> Result<Option<Template>,sqlx::Error> {
+    sqlx::query_as!(
+        Template,
+        r#"
+        SELECT name, html_layout, text_layout
+        FROM email_templates
+        WHERE email_template_id = $1
+        "#,
+        template_id
+    )
+        .fetch_optional(pool)
+        .await
+}
@@ -0,0 +1,213 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::EmailLayout, routes::{e500, see_other}};
+
+
+#[derive(serde::Deserialize)]
+pub struct TemplateFormData {
+    name:String,
+    html_layout:String,
+    text_layout:String,
+}
+
+impl TemplateFormData {
+    fn parse(self) -> Result<(String,EmailLayout),String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("The template needs a name.".to_string());
+        }
+        Ok((name, EmailLayout::parse(self.html_layout, self.text_layout)?))
+    }
+}
+
+#[tracing::instrument(
+    name = "Create an email template",
+    skip(form,pool)
+)]
+pub async fn create_template(
+    form:web::Form<TemplateFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let (name, layout) = match form.0.parse() {
+        Ok(template) => template,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/templates/new"));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO email_templates (email_template_id, name, html_layout, text_layout)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        Uuid::new_v4(),
+        name,
+        layout.html_layout,
+        layout.text_layout
+    )
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(_) => {
+            FlashMessage::info("The template has been saved.").send();
+            Ok(see_other("/admin/templates"))
+        }
+        Err(e) if is_unique_violation(&e) => {
+            FlashMessage::error(format!(
+                "A template named {} already exists.",
+                htmlescape::encode_minimal(&name)
+            )).send();
+            Ok(see_other("/admin/templates/new"))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
+
+#[tracing::instrument(
+    name = "Update an email template",
+    skip(form,pool)
+)]
+pub async fn update_template(
+    template_id:web::Path<Uuid>,
+    form:web::Form<TemplateFormData>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let editor = format!("/admin/templates/{}", template_id);
+    let (name, layout) = match form.0.parse() {
+        Ok(template) => template,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&editor));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        UPDATE email_templates
+        SET name = $2, html_layout = $3, text_layout = $4, updated_at = now()
+        WHERE email_template_id = $1
+        "#,
+        template_id,
+        name,
+        layout.html_layout,
+        layout.text_layout
+    )
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(_) => {
+            FlashMessage::info("The template has been saved.").send();
+            Ok(see_other("/admin/templates"))
+        }
+        Err(e) if is_unique_violation(&e) => {
+            FlashMessage::error(format!(
+                "A template named {} already exists.",
+                htmlescape::encode_minimal(&name)
+            )).send();
+            Ok(see_other(&editor))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
+
+/// Templates still used by an issue that has not been sent are kept.
+#[tracing::instrument(
+    name = "Delete an email template",
+    skip(pool)
+)]
+pub async fn delete_template(
+    template_id:web::Path<Uuid>,
+    pool:web::Data<PgPool>,
+) -> Result<HttpResponse,actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Locked, so that none of them is saved or published while the template
+    // goes away.
+    let n_pending = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id
+        FROM newsletter_issues
+        WHERE email_template_id = $1
+        AND delivery_state IN ('draft', 'scheduled', 'sending', 'paused')
+        FOR UPDATE
+        "#,
+        template_id
+    )
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .len();
+    if n_pending > 0 {
+        FlashMessage::error(format!(
+            "The template is still used by {} issues that have not been sent yet.",
+            n_pending
+        )).send();
+        return Ok(see_other("/admin/templates"));
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET email_template_id = NULL
+        WHERE email_template_id = $1
+        AND delivery_state NOT IN ('draft', 'scheduled', 'sending', 'paused')
+        "#,
+        template_id
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    let result = sqlx::query!(
+        r#"DELETE FROM email_templates WHERE email_template_id = $1"#,
+        template_id
+    )
+        .execute(&mut *transaction)
+        .await;
+    match result {
+        Ok(_) => {}
+        // An issue started using the template in the meantime.
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            FlashMessage::error("The template is still used by an issue that has not been sent yet.").send();
+            return Ok(see_other("/admin/templates"));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The template has been deleted.").send();
+    Ok(see_other("/admin/templates"))
+}
+
+pub const UNKNOWN_TEMPLATE: &str = "The template does not exist anymore.";
+
+/// Whether the template of an issue form still exists. A shared lock keeps
+/// it from being deleted until the issue is saved.
+#[tracing::instrument(skip(connection))]
+pub async fn lock_template(
+    connection:&mut PgConnection,
+    template_id:Option<Uuid>
+) -> Result<bool,sqlx::Error> {
+    let Some(template_id) = template_id else {
+        return Ok(true);
+    };
+    let template = sqlx::query!(
+        r#"SELECT email_template_id FROM email_templates WHERE email_template_id = $1 FOR SHARE"#,
+        template_id
+    )
+        .fetch_optional(connection)
+        .await?;
+    Ok(template.is_some())
+}
+
+/// Reads the `email_template_id` field of a form, where empty means none.
+pub fn parse_template_id(s:&str) -> Result<Option<Uuid>,uuid::Error> {
+    match s.trim() {
+        "" => Ok(None),
+        s => Uuid::parse_str(s).map(Some),
+    }
+}
+
+fn is_unique_violation(e:&sqlx::Error) -> bool {
+    e.as_database_error().is_some_and(|e| e.is_unique_violation())
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+
+use actix_web::{http::{header::{self, HeaderMap, HeaderValue}, StatusCode}, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use subtle::ConstantTimeEq;
+
+use crate::{authentication::Credentials, configuration::EmailWebhookSettings, issue_delivery_work::DeliveryStatus, routes::error_chain_fmt};
+
+
+/// The subset of a Postmark Bounce or SpamComplaint webhook we act upon.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProviderEvent {
+    record_type: String,
+    #[serde(rename = "Type", default)]
+    bounce_type: Option<String>,
+    email: String,
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<String>,
+}
+
+enum EventKind {
+    HardBounce,
+    SoftBounce,
+    SpamComplaint,
+    Other,
+}
+
+impl ProviderEvent {
+    fn kind(&self) -> EventKind {
+        match (self.record_type.as_str(), self.bounce_type.as_deref()) {
+            ("SpamComplaint", _) | ("Bounce", Some("SpamComplaint")) => EventKind::SpamComplaint,
+            ("Bounce", Some("HardBounce" | "BadEmailAddress")) => EventKind::HardBounce,
+            ("Bounce", Some("SoftBounce" | "Transient" | "DnsError")) => EventKind::SoftBounce,
+            _ => EventKind::Other,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("Failed Authentication.")]
+    AuthenticationError(#[source] anyhow::Error),
+}
+
+impl fmt::Debug for WebhookError {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            WebhookError::AuthenticationError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
+
+fn basic_authentication(headers:&HeaderMap) -> Result<Credentials,anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: SecretString::from(password),
+    })
+}
+
+/// Compares in constant time, so that response times say nothing about
+/// how much of the password was right.
+fn verify_credentials(
+    credentials:&Credentials,
+    settings:&EmailWebhookSettings
+) -> Result<(),anyhow::Error> {
+    let username_matches = credentials.username.as_bytes().ct_eq(settings.username.as_bytes());
+    let password_matches = credentials.password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(settings.password.expose_secret().as_bytes());
+    if !bool::from(username_matches & password_matches) {
+        anyhow::bail!("Invalid webhook credentials.");
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Handle an email provider event",
+    skip(request,event,pool,settings),
+    fields(record_type = %event.record_type, bounce_type = ?event.bounce_type)
+)]
+pub async fn email_provider_webhook(
+    request:HttpRequest,
+    event:web::Json<ProviderEvent>,
+    pool:web::Data<PgPool>,
+    settings:web::Data<EmailWebhookSettings>,
+) -> Result<HttpResponse,WebhookError> {
+    let credentials = basic_authentication(request.headers())
+        .map_err(WebhookError::AuthenticationError)?;
+    verify_credentials(&credentials, &settings)
+        .map_err(WebhookError::AuthenticationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let delivery_status = match event.kind() {
+        EventKind::HardBounce => {
+            suppress_subscriber(&mut transaction, &event.email, "bounced", "hard_bounce")
+                .await
+                .context("Failed to suppress a hard-bounced subscriber")?;
+            DeliveryStatus::Bounced
+        }
+        EventKind::SpamComplaint => {
+            suppress_subscriber(&mut transaction, &event.email, "complained", "spam_complaint")
+                .await
+                .context("Failed to suppress a complaining subscriber")?;
+            DeliveryStatus::Complained
+        }
+        EventKind::SoftBounce => {
+            let soft_bounces = count_soft_bounce(&mut transaction, &event.email, event.message_id.as_deref())
+                .await
+                .context("Failed to count a soft bounce")?;
+            if soft_bounces.is_some_and(|n| n >= settings.soft_bounce_threshold) {
+                suppress_subscriber(&mut transaction, &event.email, "bounced", "soft_bounces")
+                    .await
+                    .context("Failed to suppress a soft-bouncing subscriber")?;
+            }
+            DeliveryStatus::SoftBounced
+        }
+        EventKind::Other => {
+            tracing::info!("Ignoring an email provider event we do not act upon.");
+            return Ok(HttpResponse::Ok().finish());
+        }
+    };
+    if let Some(message_id) = &event.message_id {
+        update_delivery_status(&mut transaction, message_id, delivery_status)
+            .await
+            .context("Failed to update the status of the delivery")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to handle an email provider event")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Moves the subscriber to `status` and adds their address to the
+/// suppression list, so that no further issues are sent to it.
+#[tracing::instrument(
+    name = "Suppress a subscriber",
+    skip(transaction)
+)]
+async fn suppress_subscriber(
+    transaction:&mut PgConnection,
+    email:&str,
+    status:&str,
+    reason:&str
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE email = $1"#,
+        email,
+        status
+    )
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_suppressions (email, reason, suppressed_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email,
+        reason
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Returns the subscriber's soft bounces so far, or `None` for unknown
+/// addresses and for a bounce whose message was already counted.
+#[tracing::instrument(
+    name = "Count a soft bounce",
+    skip(transaction)
+)]
+async fn count_soft_bounce(
+    transaction:&mut PgConnection,
+    email:&str,
+    provider_message_id:Option<&str>
+) -> Result<Option<i32>,sqlx::Error> {
+    if let Some(provider_message_id) = provider_message_id {
+        let first_seen = sqlx::query!(
+            r#"
+            INSERT INTO soft_bounce_events (provider_message_id, email)
+            VALUES ($1, $2)
+            ON CONFLICT (provider_message_id) DO NOTHING
+            "#,
+            provider_message_id,
+            email
+        )
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected() > 0;
+        if !first_seen {
+            tracing::info!("Ignoring a soft bounce that was already counted.");
+            return Ok(None);
+        }
+    }
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET soft_bounces = soft_bounces + 1
+        WHERE email = $1
+        RETURNING soft_bounces
+        "#,
+        email
+    )
+        .fetch_optional(transaction)
+        .await?;
+    Ok(row.map(|r| r.soft_bounces))
+}
+
+#[tracing::instrument(
+    name = "Update the status of a delivery",
+    skip(transaction)
+)]
+async fn update_delivery_status(
+    transaction:&mut PgConnection,
+    provider_message_id:&str,
+    status:DeliveryStatus
+) -> Result<(),sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE deliveries SET status = $2 WHERE provider_message_id = $1"#,
+        provider_message_id,
+        status.as_str()
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
@@ -7,7 +7,7 @@ use sqlx::{postgres::PgPoolOptions, PgPool};
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
 use actix_session::{storage::{RedisSessionStore},  SessionMiddleware};
 
-use crate::{configuration::{DatabaseSetting, Setting}, email_client::EmailClient, health_check, middleware::reject_anonymous_users, routes::{confirm, dashboard_page, e404, home, login, login_form, logout, publish_form, publish_newsletter, reset, reset_form, subscribe}};
+use crate::{configuration::{DatabaseSetting, EmailWebhookSettings, Setting}, email_client::EmailClient, health_check, middleware::reject_anonymous_users, routes::{create_list, delete_list, lists_page, tags_page, tag_subscribers, untag_subscribers, segments_page, create_segment, delete_segment, archive_page, archive_search_page, admin_search_page, atom_feed, rss_feed, archived_issue_page, hide_from_archive, show_in_archive, confirm, create_draft, create_template, delete_template, edit_template_form, new_template_form, templates_page, update_template, dashboard_page, drafts_page, edit_draft_form, publish_draft, update_draft, e404, email_provider_webhook, failed_deliveries_page, requeue_failed_deliveries, home, issue_progress, issue_progress_page, pause_issue, resume_issue, cancel_issue, scheduled_issues_page, reschedule_issue, unschedule_issue, login, login_form, logout, publish_form, publish_newsletter, preview_newsletter, send_test_email, reset, reset_form, subscribe, unsubscribe, unsubscribe_form}};
 
 pub struct Application {
     pub server:Server,
@@ -22,19 +22,7 @@ impl Application {
             .idle_timeout(std::time::Duration::from_secs(2))
             .connect_lazy_with(configuration.database.connection_string());
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid Sender Email address");
-
-        let timeout = std::time::Duration::from_secs(2);
-
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url, 
-            sender_email, 
-            configuration.email_client.authorization_token,
-            timeout
-        );
+        let email_client = configuration.email_client.client();
 
         let address = format!("{}:{}",configuration.application.host,configuration.application.port);
 
@@ -45,6 +33,7 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.email_webhook,
             configuration.redis_uri
             )
             .await?;
@@ -71,6 +60,7 @@ impl Application {
         email_client:EmailClient,
         base_url: String,
         hmac_secret: HmacSecret,
+        email_webhook: EmailWebhookSettings,
         redis_uri:SecretString
     )
         -> Result<Server,anyhow::Error> {
@@ -93,6 +83,14 @@ impl Application {
                     .route("/health_check", web::get().to(health_check))
                     .route("/subscriptions", web::post().to(subscribe))
                     .route("/subscriptions/confirm", web::get().to(confirm))
+                    .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+                    .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+                    .route("/archive", web::get().to(archive_page))
+                    .route("/archive/search", web::get().to(archive_search_page))
+                    .route("/archive/{slug}", web::get().to(archived_issue_page))
+                    .route("/feed.rss", web::get().to(rss_feed))
+                    .route("/feed.atom", web::get().to(atom_feed))
+                    .route("/webhooks/email-provider", web::post().to(email_provider_webhook))
                     .route("/login", web::get().to(login_form))
                     .route("/login", web::post().to(login))
                     .service(
@@ -101,6 +99,41 @@ impl Application {
                         .route("/dashboard",web::get().to(dashboard_page))
                         .route("/newsletter", web::post().to(publish_newsletter))
                         .route("/newsletter",web::get().to(publish_form))
+                        .route("/newsletter/test", web::post().to(send_test_email))
+                        .route("/newsletter/preview", web::post().to(preview_newsletter))
+                        .route("/drafts", web::get().to(drafts_page))
+                        .route("/drafts", web::post().to(create_draft))
+                        .route("/drafts/{draft_id}", web::get().to(edit_draft_form))
+                        .route("/drafts/{draft_id}", web::post().to(update_draft))
+                        .route("/drafts/{draft_id}/publish", web::post().to(publish_draft))
+                        .route("/templates", web::get().to(templates_page))
+                        .route("/templates", web::post().to(create_template))
+                        .route("/templates/new", web::get().to(new_template_form))
+                        .route("/templates/{template_id}", web::get().to(edit_template_form))
+                        .route("/templates/{template_id}", web::post().to(update_template))
+                        .route("/templates/{template_id}/delete", web::post().to(delete_template))
+                        .route("/lists", web::get().to(lists_page))
+                        .route("/lists", web::post().to(create_list))
+                        .route("/lists/{list_id}/delete", web::post().to(delete_list))
+                        .route("/tags", web::get().to(tags_page))
+                        .route("/tags", web::post().to(tag_subscribers))
+                        .route("/tags/remove", web::post().to(untag_subscribers))
+                        .route("/segments", web::get().to(segments_page))
+                        .route("/segments", web::post().to(create_segment))
+                        .route("/segments/{segment_id}/delete", web::post().to(delete_segment))
+                        .route("/deliveries/failed", web::get().to(failed_deliveries_page))
+                        .route("/deliveries/failed", web::post().to(requeue_failed_deliveries))
+                        .route("/search", web::get().to(admin_search_page))
+                        .route("/issues/scheduled", web::get().to(scheduled_issues_page))
+                        .route("/issues/{issue_id}", web::get().to(issue_progress_page))
+                        .route("/issues/{issue_id}/progress", web::get().to(issue_progress))
+                        .route("/issues/{issue_id}/pause", web::post().to(pause_issue))
+                        .route("/issues/{issue_id}/resume", web::post().to(resume_issue))
+                        .route("/issues/{issue_id}/cancel", web::post().to(cancel_issue))
+                        .route("/issues/{issue_id}/reschedule", web::post().to(reschedule_issue))
+                        .route("/issues/{issue_id}/unschedule", web::post().to(unschedule_issue))
+                        .route("/issues/{issue_id}/archive/hide", web::post().to(hide_from_archive))
+                        .route("/issues/{issue_id}/archive/show", web::post().to(show_in_archive))
                         .route("/reset",web::get().to(reset))
                         .route("/reset", web::post().to(reset_form))
                         .route("/logout", web::post().to(logout))
@@ -111,6 +144,7 @@ impl Application {
                     )
                     .app_data(data.clone())
                     .app_data(web::Data::new(hmac_secret.clone()))
+                    .app_data(web::Data::new(email_webhook.clone()))
                     .app_data(email_client.clone())
                     .app_data(base_url.clone())
             })
@@ -0,0 +1,187 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+
+pub async fn publish_issue(app:&TestApp, title:&str) -> Uuid {
+    let body = serde_json::json!({
+        "title":title,
+        "html_content":"<p>Hello {{ subscriber.name | default: \"reader\" }}</p>",
+        "text_content":"Hello",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    sqlx::query!(
+        "SELECT newsletter_issues_id FROM newsletter_issues ORDER BY updated_at DESC, published_at DESC LIMIT 1"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issues_id
+}
+
+#[tokio::test]
+async fn published_issues_are_in_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Hello, World!").await;
+    app.post_draft("", &serde_json::json!({
+        "title":"Secret draft",
+        "html_content":"<p>Draft</p>",
+        "text_content":"Draft",
+    })).await;
+    app.post_to_logout().await;
+
+    let html = app.get_archive_html("").await;
+    assert!(html.contains(r#"<a href="/archive/hello-world">Hello, World!</a>"#));
+    assert!(!html.contains("Secret draft"));
+
+    let html = app.get_archive_html("/hello-world").await;
+    assert!(html.contains("<h1>Hello, World!</h1>"));
+    assert!(html.contains("<p>Hello reader</p>"));
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_distinct_slugs() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Weekly news").await;
+    publish_issue(&app, "Weekly news").await;
+    publish_issue(&app, "Weekly news").await;
+
+    for slug in ["weekly-news", "weekly-news-2", "weekly-news-3"] {
+        let response = app.get_archive(&format!("/{}", slug)).await;
+        assert_eq!(response.status().as_u16(),200);
+    }
+}
+
+#[tokio::test]
+async fn issues_published_at_once_with_the_same_title_get_distinct_slugs() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let body = || serde_json::json!({
+        "title":"Breaking news",
+        "html_content":"<p>Hello</p>",
+        "text_content":"Hello",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+
+    let (first_body, second_body) = (body(), body());
+    let (first, second) = tokio::join!(app.post_newsletter(&first_body), app.post_newsletter(&second_body));
+    assert_is_redirect_to(&first, "/admin/newsletter");
+    assert_is_redirect_to(&second, "/admin/newsletter");
+
+    for slug in ["breaking-news", "breaking-news-2"] {
+        let response = app.get_archive(&format!("/{}", slug)).await;
+        assert_eq!(response.status().as_u16(),200);
+    }
+}
+
+#[tokio::test]
+async fn slugs_backfilled_by_the_archive_migration_drop_non_ascii_letters() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Café").await;
+    assert_eq!(app.get_archive("/cafe").await.status().as_u16(),200);
+
+    // Run the backfill of the migration again, as on an issue published
+    // before the archive existed.
+    sqlx::query!("UPDATE newsletter_issues SET slug = NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let migration = include_str!("../../migrations/20261018170000_add_archive_to_newsletter_issues.sql");
+    let start = migration.find("UPDATE newsletter_issues").unwrap();
+    let end = start + migration[start..].find(';').unwrap();
+    sqlx::query(&migration[start..end])
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(app.get_archive("/caf").await.status().as_u16(),200);
+}
+
+#[tokio::test]
+async fn hidden_issues_are_not_in_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "Hidden issue").await.to_string();
+
+    let response = app.post_issue_action(&issue_id, "archive/hide").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    assert!(app.get_issue_page_html(&issue_id).await.contains("Hidden from the public archive."));
+    assert!(!app.get_archive_html("").await.contains("Hidden issue"));
+    assert_eq!(app.get_archive("/hidden-issue").await.status().as_u16(),404);
+
+    app.post_issue_action(&issue_id, "archive/show").await;
+    assert!(app.get_archive_html("").await.contains("Hidden issue"));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for n in 0..12 {
+        publish_issue(&app, &format!("Issue {}", n)).await;
+    }
+
+    let first_page = app.get_archive_html("").await;
+    assert_eq!(first_page.matches("<li>").count(),10);
+    assert!(first_page.contains(r#"<a href="/archive?page=2">Older issues</a>"#));
+    assert!(!first_page.contains("Newer issues"));
+
+    let second_page = app.get_archive_html("?page=2").await;
+    assert_eq!(second_page.matches("<li>").count(),2);
+    assert!(second_page.contains(r#"<a href="/archive?page=1">Newer issues</a>"#));
+    assert!(!second_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_list_or_segment_stay_out_of_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_list("Members").await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+        .to_string();
+    app.post_segment("", &serde_json::json!({"name":"Blog","signup_source":"blog"})).await;
+    let segment_id = sqlx::query!("SELECT segment_id FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .segment_id
+        .to_string();
+
+    for (title, audience) in [("Members only", ("list_ids", list_id.as_str())), ("Bloggers only", ("segment_id", segment_id.as_str()))] {
+        let idempotency_key = Uuid::new_v4().to_string();
+        let body = [
+            ("title",title),
+            ("html_content","<p>Private</p>"),
+            ("text_content","Private"),
+            audience,
+            ("idempotency_key",&idempotency_key),
+        ];
+        let response = app.post_newsletter(&body).await;
+        assert_is_redirect_to(&response, "/admin/newsletter");
+    }
+    publish_issue(&app, "For everyone").await;
+    app.post_to_logout().await;
+
+    let html = app.get_archive_html("").await;
+    assert!(html.contains("For everyone"));
+    assert!(!html.contains("Members only"));
+    assert!(!html.contains("Bloggers only"));
+    assert_eq!(app.get_archive("/members-only").await.status().as_u16(),404);
+
+    let feed = app.get_feed("rss").await.text().await.unwrap();
+    assert!(feed.contains("For everyone"));
+    assert!(!feed.contains("Members only"));
+    let html = app.get_archive_html("/search?q=private").await;
+    assert!(html.contains("No issue matches"));
+    assert!(!html.contains("Members only"));
+    assert!(!html.contains("Bloggers only"));
+}
@@ -0,0 +1,106 @@
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp}, newsletter::publish_newsletter_to_confirmed_subscriber};
+
+
+async fn create_failed_delivery(app:&TestApp) -> (String,String) {
+    publish_newsletter_to_confirmed_subscriber(app).await;
+    sqlx::query!(
+        "UPDATE issues_delivery_queue SET n_retries = $1",
+        app.issue_delivery.max_retries as i32
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let failure = sqlx::query!(
+        "SELECT newsletter_issues_id, subscriber_email FROM issues_delivery_failures"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (failure.newsletter_issues_id.to_string(),failure.subscriber_email)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+    let response = app.get_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed_per_issue() {
+    let app = spawn_app().await;
+    let (_,email) = create_failed_delivery(&app).await;
+
+    let html = app.get_failed_deliveries_html().await;
+    assert!(html.contains("<h2>Newsletter Title</h2>"));
+    assert!(html.contains(&email));
+}
+
+#[tokio::test]
+async fn selected_failed_deliveries_are_re_enqueued() {
+    let app = spawn_app().await;
+    let (issue_id,email) = create_failed_delivery(&app).await;
+
+    let body = serde_urlencoded::to_string([
+        ("tasks",format!("{}/{}",issue_id,email))
+    ]).unwrap();
+    let response = app.post_requeue_failed_deliveries(body).await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+
+    let html = app.get_failed_deliveries_html().await;
+    assert!(html.contains("<p><i>Re-enqueued 1 deliveries.</i></p>"));
+    assert!(html.contains("There are no failed deliveries."));
+
+    let queued = sqlx::query!("SELECT subscriber_email, n_retries FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should be back in the queue");
+    assert_eq!(queued.subscriber_email,email);
+    assert_eq!(queued.n_retries,0);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+}
+
+#[tokio::test]
+async fn failed_deliveries_of_cancelled_issues_are_not_re_enqueued() {
+    let app = spawn_app().await;
+    let (issue_id,email) = create_failed_delivery(&app).await;
+    sqlx::query!("UPDATE newsletter_issues SET delivery_state = 'cancelled'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let body = serde_urlencoded::to_string([
+        ("tasks",format!("{}/{}",issue_id,email))
+    ]).unwrap();
+    app.post_requeue_failed_deliveries(body).await;
+
+    let html = app.get_failed_deliveries_html().await;
+    assert!(html.contains("<p><i>Re-enqueued 0 deliveries.</i></p>"));
+    assert!(html.contains("1 deliveries belong to cancelled issues and were not re-enqueued."));
+    assert!(html.contains(&email));
+
+    let n_queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued,0);
+}
@@ -0,0 +1,137 @@
+use uuid::Uuid;
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, TestApp}, newsletter::create_confirmed_subscriber};
+
+
+fn draft_body(title:&str) -> serde_json::Value {
+    serde_json::json!({
+        "title":title,
+        "html_content":"<p> Draft body as HTML </p>",
+        "text_content":"Draft body as text",
+    })
+}
+
+async fn create_draft(app:&TestApp, title:&str) -> String {
+    let response = app.post_draft("", &draft_body(title)).await;
+    let location = response.headers()["Location"].to_str().unwrap().to_string();
+    location.strip_prefix("/admin/drafts/").expect("Not redirected to the draft").to_string()
+}
+
+async fn queued_deliveries(app:&TestApp) -> i64 {
+    sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_drafts() {
+    let app = spawn_app().await;
+    let response = app.api_client
+        .get(format!("{}/admin/drafts",&app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn drafts_are_saved_without_being_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let draft_id = create_draft(&app, "Draft Title").await;
+    assert!(app.get_drafts_html(&format!("/{}", draft_id)).await.contains("The draft has been saved."));
+    assert_eq!(queued_deliveries(&app).await,0);
+
+    let html = app.get_drafts_html("").await;
+    assert!(html.contains(&format!(r#"<a href="/admin/drafts/{}">Draft Title</a>"#, draft_id)));
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Draft Title").await;
+
+    let response = app.post_draft(&format!("/{}", draft_id), &draft_body("Better \"Title\"")).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", draft_id));
+
+    let html = app.get_drafts_html(&format!("/{}", draft_id)).await;
+    assert!(html.contains("&quot;Title&quot;"));
+    assert!(!html.contains(r#""Title""#));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_enqueues_it_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Draft Title").await;
+
+    let body = serde_json::json!({"idempotency_key":Uuid::new_v4().to_string()});
+    let response = app.post_draft(&format!("/{}/publish", draft_id), &body).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html = app.get_drafts_html("").await;
+    assert!(html.contains("The newsletter issue has been published! 1 deliveries have been enqueued."));
+    assert!(html.contains("There are no drafts."));
+    assert_eq!(queued_deliveries(&app).await,1);
+
+    // Submitting the same form twice is a no-op.
+    let response = app.post_draft(&format!("/{}/publish", draft_id), &body).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    assert_eq!(queued_deliveries(&app).await,1);
+
+    let body = serde_json::json!({"idempotency_key":Uuid::new_v4().to_string()});
+    app.post_draft(&format!("/{}/publish", draft_id), &body).await;
+    assert!(app.get_drafts_html("").await.contains("Only a draft can be published."));
+    assert_eq!(queued_deliveries(&app).await,1);
+
+    let response = app.post_draft(&format!("/{}", draft_id), &draft_body("Too late")).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+}
+
+#[tokio::test]
+async fn drafts_can_be_scheduled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Draft Title").await;
+
+    let body = serde_json::json!({
+        "idempotency_key":Uuid::new_v4().to_string(),
+        "scheduled_for":"2999-01-01T09:30",
+    });
+    app.post_draft(&format!("/{}/publish", draft_id), &body).await;
+    assert!(app.get_drafts_html("").await.contains("The newsletter issue has been scheduled for 2999-01-01 09:30 UTC."));
+    assert_eq!(queued_deliveries(&app).await,0);
+    assert!(app.get_scheduled_issues_html().await.contains("<td>Draft Title</td>"));
+}
+
+#[tokio::test]
+async fn drafts_keep_their_markdown_until_published() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let mut body = draft_body("Draft Title");
+    body["markdown_content"] = "Some *Markdown*".into();
+    body["html_content"] = "".into();
+    let response = app.post_draft("", &body).await;
+    let draft_id = response.headers()["Location"].to_str().unwrap()
+        .strip_prefix("/admin/drafts/").unwrap().to_string();
+
+    let html = app.get_drafts_html(&format!("/{}", draft_id)).await;
+    assert!(html.contains(r#"name="markdown_content">Some *Markdown*</textarea>"#));
+    assert!(html.contains(r#"name="html_content"></textarea>"#));
+
+    let body = serde_json::json!({"idempotency_key":Uuid::new_v4().to_string()});
+    app.post_draft(&format!("/{}/publish", draft_id), &body).await;
+    let issue = sqlx::query!("SELECT html_content, text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.html_content,"<p>Some <em>Markdown</em></p>");
+    assert_eq!(issue.text_content,"Draft body as text");
+}
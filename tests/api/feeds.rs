@@ -0,0 +1,91 @@
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+use crate::{archive::publish_issue, helpers::{spawn_app, TestApp}};
+
+
+async fn get_conditionally(app:&TestApp, feed:&str, header:reqwest::header::HeaderName, value:&str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/feed.{}",&app.address,feed))
+        .header(header, value)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_feeds_carry_published_issues_newest_first() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first = publish_issue(&app, "First issue").await;
+    let second = publish_issue(&app, "Second & last").await;
+    sqlx::query!("UPDATE newsletter_issues SET published_at = published_at + interval '1 minute' WHERE newsletter_issues_id = $1", second)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_feed("rss").await;
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(response.headers()["Content-Type"],"application/rss+xml; charset=utf-8");
+    let rss = response.text().await.unwrap();
+    let first_guid = format!(r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#, first);
+    let second_guid = format!(r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#, second);
+    assert!(rss.find(&second_guid).unwrap() < rss.find(&first_guid).unwrap());
+    assert!(rss.contains("<title>Second &amp; last</title>"));
+    assert!(rss.contains(&format!("<link>{}/archive/first-issue</link>", app.base_url)));
+    assert!(rss.contains("<description>&lt;p&gt;Hello reader&lt;/p&gt;</description>"));
+
+    let atom = app.get_feed("atom").await.text().await.unwrap();
+    assert!(atom.find(&format!("<id>urn:uuid:{}</id>", second)).unwrap() < atom.find(&format!("<id>urn:uuid:{}</id>", first)).unwrap());
+    assert!(atom.contains(r#"<content type="html">&lt;p&gt;Hello reader&lt;/p&gt;</content>"#));
+}
+
+#[tokio::test]
+async fn hidden_issues_and_drafts_are_not_in_the_feeds() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let hidden = publish_issue(&app, "Hidden issue").await;
+    app.post_issue_action(&hidden.to_string(), "archive/hide").await;
+    app.post_draft("", &serde_json::json!({
+        "title":"Secret draft",
+        "html_content":"<p>Draft</p>",
+        "text_content":"Draft",
+    })).await;
+
+    for feed in ["rss", "atom"] {
+        let body = app.get_feed(feed).await.text().await.unwrap();
+        assert!(!body.contains("Hidden issue"));
+        assert!(!body.contains("Secret draft"));
+    }
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "First issue").await;
+
+    for feed in ["rss", "atom"] {
+        let response = app.get_feed(feed).await;
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        let last_modified = response.headers()[LAST_MODIFIED].to_str().unwrap().to_string();
+
+        let response = get_conditionally(&app, feed, IF_NONE_MATCH, &etag).await;
+        assert_eq!(response.status().as_u16(),304);
+        assert!(response.text().await.unwrap().is_empty());
+        let response = get_conditionally(&app, feed, IF_MODIFIED_SINCE, &last_modified).await;
+        assert_eq!(response.status().as_u16(),304);
+    }
+}
+
+#[tokio::test]
+async fn feeds_are_sent_again_once_changed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "First issue").await;
+    let etag = app.get_feed("rss").await.headers()[ETAG].to_str().unwrap().to_string();
+
+    app.post_issue_action(&issue_id.to_string(), "archive/hide").await;
+    let response = get_conditionally(&app, "rss", IF_NONE_MATCH, &etag).await;
+    assert_eq!(response.status().as_u16(),200);
+    assert_ne!(response.headers()[ETAG].to_str().unwrap(),etag);
+}
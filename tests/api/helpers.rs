@@ -3,12 +3,13 @@
 
 use argon2::{password_hash::SaltString, Argon2, Params, PasswordHasher};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use reqwest::{redirect::{self, Policy}, Response, Url};
 use sqlx::{Connection, PgConnection, PgPool,Executor};
 use uuid::Uuid;
-use wiremock::{MockServer, Request};
-use zero2production::{configuration::{get_configuration, DatabaseSetting}, email_client::EmailClient, issue_delivery_work::{try_execute_task, ExecutionOutcome}, startup::get_connection_pool, telemetry::{get_subscriber, init_subscriber}};
-use zero2production::startup::Application;
+use wiremock::{MockServer, Request, Respond, ResponseTemplate};
+use zero2production::{configuration::{get_configuration, DatabaseSetting, EmailWebhookSettings, IssueDeliverySettings}, email_client::EmailClient, issue_delivery_work::{try_execute_task, ExecutionOutcome}, issue_scheduler::try_publish_scheduled_issue, startup::get_connection_pool, telemetry::{get_subscriber, init_subscriber}};
+use zero2production::startup::{Application, HmacSecret};
 use argon2::password_hash::rand_core::OsRng;
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -29,7 +30,11 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user : TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: HmacSecret,
+    pub issue_delivery: IssueDeliverySettings,
+    pub email_webhook: EmailWebhookSettings,
 }
 
 pub struct TestUser {
@@ -89,13 +94,23 @@ impl TestApp {
     pub async fn dispatch_all_pending_email(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client).await.unwrap() {
+                try_execute_task(
+                    &self.db_pool,
+                    &self.email_client,
+                    &self.base_url,
+                    &self.hmac_secret,
+                    &self.issue_delivery
+                ).await.unwrap() {
                     break;
             }
         }
 
     }
 
+    pub async fn publish_due_issues(&self) {
+        while try_publish_scheduled_issue(&self.db_pool).await.unwrap().is_some() {}
+    }
+
     pub async fn post_to_logout(&self) -> reqwest::Response {
         let response = self.api_client
             .post(format!("{}/admin/logout",self.address))
@@ -194,6 +209,33 @@ impl TestApp {
             .expect("Failed to execute to newsletter");
         response
     }
+    pub async fn post_test_email<Body>(&self,body:&Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletter/test",self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send a test email")
+    }
+
+    pub async fn post_preview<Body>(&self,body:&Body) -> String
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletter/preview",self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to preview the issue")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn get_newsletter(&self) -> reqwest::Response {
         let response = self.api_client
             .get(format!("{}/admin/newsletter",&self.address))
@@ -210,6 +252,246 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries/failed",&self.address))
+            .send()
+            .await
+            .expect("Failed to get failed deliveries")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_failed_deliveries().await.text().await.unwrap()
+    }
+
+    pub async fn post_requeue_failed_deliveries(&self,body:String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/deliveries/failed",&self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to re-enqueue failed deliveries")
+    }
+
+    pub async fn get_issue_page(&self,issue_id:&str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}",&self.address,issue_id))
+            .send()
+            .await
+            .expect("Failed to get the issue page")
+    }
+
+    pub async fn get_issue_page_html(&self,issue_id:&str) -> String {
+        self.get_issue_page(issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn get_issue_progress(&self,issue_id:&str) -> serde_json::Value {
+        self.api_client
+            .get(format!("{}/admin/issues/{}/progress",&self.address,issue_id))
+            .send()
+            .await
+            .expect("Failed to get the issue progress")
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_scheduled_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/issues/scheduled",&self.address))
+            .send()
+            .await
+            .expect("Failed to get the scheduled issues")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_issue(&self,issue_id:&str,scheduled_for:&str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/reschedule",&self.address,issue_id))
+            .form(&[("scheduled_for",scheduled_for)])
+            .send()
+            .await
+            .expect("Failed to reschedule the issue")
+    }
+
+    pub async fn post_draft<Body>(&self,path:&str,body:&Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts{}",&self.address,path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to post the draft")
+    }
+
+    pub async fn get_drafts_html(&self,path:&str) -> String {
+        self.api_client
+            .get(format!("{}/admin/drafts{}",&self.address,path))
+            .send()
+            .await
+            .expect("Failed to get the drafts")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_template<Body>(&self,path:&str,body:&Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/templates{}",&self.address,path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to post the template")
+    }
+
+    pub async fn get_templates_html(&self,path:&str) -> String {
+        self.api_client
+            .get(format!("{}/admin/templates{}",&self.address,path))
+            .send()
+            .await
+            .expect("Failed to get the templates")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_archive(&self,path:&str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/archive{}",&self.address,path))
+            .send()
+            .await
+            .expect("Failed to get the archive")
+    }
+
+    pub async fn get_archive_html(&self,path:&str) -> String {
+        self.get_archive(path).await.text().await.unwrap()
+    }
+
+    pub async fn get_feed(&self,feed:&str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/feed.{}",&self.address,feed))
+            .send()
+            .await
+            .expect("Failed to get the feed")
+    }
+
+    pub async fn get_admin_search_html(&self,q:&str) -> String {
+        self.api_client
+            .get(format!("{}/admin/search",&self.address))
+            .query(&[("q",q)])
+            .send()
+            .await
+            .expect("Failed to search the issues")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_list(&self,name:&str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists",&self.address))
+            .form(&serde_json::json!({"name":name}))
+            .send()
+            .await
+            .expect("Failed to post the list")
+    }
+
+    pub async fn post_list_action(&self,list_id:&Uuid,action:&str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists/{}/{}",&self.address,list_id,action))
+            .send()
+            .await
+            .expect("Failed to post the list action")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists",&self.address))
+            .send()
+            .await
+            .expect("Failed to get the lists")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_tags<Body>(&self,path:&str,body:&Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/tags{}",&self.address,path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to post the tags")
+    }
+
+    pub async fn get_tags_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/tags",&self.address))
+            .send()
+            .await
+            .expect("Failed to get the tags")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_segment<Body>(&self,path:&str,body:&Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/segments{}",&self.address,path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to post the segment")
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/segments",&self.address))
+            .send()
+            .await
+            .expect("Failed to get the segments")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_issue_action(&self,issue_id:&str,action:&str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/{}",&self.address,issue_id,action))
+            .send()
+            .await
+            .expect("Failed to change the delivery of the issue")
+    }
+
+    pub async fn post_email_provider_event(&self,body:&serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email-provider",&self.address))
+            .basic_auth(
+                &self.email_webhook.username,
+                Some(self.email_webhook.password.expose_secret())
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to post an email provider event")
+    }
+
     pub async fn post_login<Body>(&self,
         body:&Body) -> reqwest::Response
     where 
@@ -251,6 +533,34 @@ impl TestApp {
 
     }
 
+    pub fn get_unsubscribe_link(
+        &self,
+        email:&serde_json::Value,
+    ) -> Url {
+        let raw_link = linkify::LinkFinder::new()
+            .links(email["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| l.as_str().to_owned())
+            .find(|l| l.contains("/subscriptions/unsubscribe"))
+            .expect("No unsubscribe link in the email");
+        let mut unsubscribe_link = Url::parse(&raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(),"127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
+    /// Every message the delivery workers sent through Postmark's batch endpoint.
+    pub async fn batch_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path() == "/email/batch")
+            .flat_map(|r| serde_json::from_slice::<Vec<serde_json::Value>>(&r.body).unwrap())
+            .collect()
+    }
+
     pub async fn test_user(&self) -> (String,String) {
         let row = sqlx::query!(
             "SELECT username, hash_password FROM users LIMIT 1",
@@ -299,7 +609,11 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client:configuration.email_client.client()
+        email_client:configuration.email_client.client(),
+        base_url:configuration.application.base_url.clone(),
+        hmac_secret:configuration.application.hmac_secret.clone(),
+        issue_delivery:configuration.issue_delivery.clone(),
+        email_webhook:configuration.email_webhook.clone(),
     };
     // add_test_users(&test_app.db_pool).await;
     test_app.test_user.store(&test_app.db_pool).await;
@@ -342,6 +656,25 @@ async fn add_test_users(pool:&PgPool) {
         .expect("Failed to create new user in function add_test_users");
 }
 
+/// Accepts every message of a Postmark batch request.
+pub struct PostmarkBatchResponder;
+
+impl Respond for PostmarkBatchResponder {
+    fn respond(&self, request:&Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .map(|m| serde_json::json!({
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": Uuid::new_v4().to_string(),
+                "To": m["To"],
+            }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
 pub fn assert_is_redirect_to(response:&Response,location:&str) {
     assert_eq!(response.status().as_u16(),303);
     assert_eq!(response.headers()["LOCATION"],location);
@@ -0,0 +1,153 @@
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp}, newsletter::{create_confirmed_subscriber, publish_newsletter_to_confirmed_subscriber}};
+
+
+async fn published_issue_id(app:&TestApp) -> String {
+    sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("No newsletter issue was published")
+        .newsletter_issues_id
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_progress_of_an_issue() {
+    let app = spawn_app().await;
+    let response = app.get_issue_page(&uuid::Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn unknown_issues_return_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app.get_issue_page(&uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(),404);
+}
+
+#[tokio::test]
+async fn progress_counts_pending_and_sent_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+    let issue_id = published_issue_id(&app).await;
+
+    let progress = app.get_issue_progress(&issue_id).await;
+    assert_eq!(progress["total"],2);
+    assert_eq!(progress["pending"],2);
+    assert_eq!(progress["sent"],0);
+    assert!(app.get_issue_page_html(&issue_id).await.contains("<h1>Newsletter Title</h1>"));
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let progress = app.get_issue_progress(&issue_id).await;
+    assert_eq!(progress["total"],2);
+    assert_eq!(progress["pending"],0);
+    assert_eq!(progress["sent"],2);
+    assert_eq!(progress["failed"],0);
+    assert_eq!(progress["state"],"completed");
+    assert!(progress["estimated_completion"].is_null());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed_on_the_issue_page() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+    let issue_id = published_issue_id(&app).await;
+    sqlx::query!(
+        "UPDATE issues_delivery_queue SET n_retries = $1",
+        app.issue_delivery.max_retries as i32
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let html = app.get_issue_page_html(&issue_id).await;
+    assert!(html.contains(r#"Failed: <span id="failed">1</span>"#));
+    assert!(html.contains(&email));
+}
+
+#[tokio::test]
+async fn a_paused_issue_is_not_delivered_until_it_is_resumed() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+    let issue_id = published_issue_id(&app).await;
+
+    let response = app.post_issue_action(&issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html = app.get_issue_page_html(&issue_id).await;
+    assert!(html.contains("<p><i>The delivery has been paused.</i></p>"));
+    assert!(html.contains(r#"<span id="state">paused</span>"#));
+
+    let paused = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+    drop(paused);
+    assert_eq!(app.get_issue_progress(&issue_id).await["pending"],1);
+
+    let response = app.post_issue_action(&issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let progress = app.get_issue_progress(&issue_id).await;
+    assert_eq!(progress["sent"],1);
+    assert_eq!(progress["state"],"completed");
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_drops_its_remaining_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+    let issue_id = published_issue_id(&app).await;
+
+    let response = app.post_issue_action(&issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html = app.get_issue_page_html(&issue_id).await;
+    assert!(html.contains("The delivery has been cancelled. 2 emails were never sent."));
+
+    let queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(queued,0);
+    let progress = app.get_issue_progress(&issue_id).await;
+    assert_eq!(progress["state"],"cancelled");
+    assert_eq!(progress["cancelled"],2);
+    assert_eq!(progress["total"],2);
+
+    let response = app.post_issue_action(&issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html = app.get_issue_page_html(&issue_id).await;
+    assert!(html.contains("Only a paused issue can be resumed."));
+}
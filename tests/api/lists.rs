@@ -0,0 +1,264 @@
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp};
+
+
+async fn create_list(app:&TestApp, name:&str) -> Uuid {
+    let response = app.post_list(name).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+async fn subscribe(app:&TestApp, email:&str, list:&str) -> reqwest::Response {
+    let body = serde_urlencoded::to_string([("name","Le Guin"),("email",email),("list",list)]).unwrap();
+    app.post_subscriptions(body).await
+}
+
+/// Subscribes `email` to `list` and follows the confirmation link.
+async fn join_list(app:&TestApp, email:&str, list:&str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    subscribe(app, email, list).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmations_link(&email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+}
+
+async fn membership_status(app:&TestApp, email:&str, list_id:Uuid) -> Option<String> {
+    sqlx::query!(
+        r#"
+        SELECT m.status
+        FROM list_memberships m JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1 AND m.list_id = $2
+        "#,
+        email,
+        list_id
+    )
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn lists_can_be_created() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_list(&app, "Weekly Digest").await;
+    let html = app.get_lists_html().await;
+    assert!(html.contains("The list has been created."));
+    assert!(html.contains("<td>Weekly Digest</td><td>weekly-digest</td>"));
+    assert!(html.contains("name=&quot;list&quot; value=&quot;weekly-digest&quot;"));
+
+    app.post_list("Weekly Digest").await;
+    assert!(app.get_lists_html().await.contains("A list named Weekly Digest already exists."));
+}
+
+#[tokio::test]
+async fn list_memberships_are_confirmed_with_the_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Weekly").await;
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    subscribe(&app, "ursula@example.com", "weekly").await.error_for_status().unwrap();
+    assert_eq!(membership_status(&app, "ursula@example.com", list_id).await.as_deref(), Some("pending_confirmations"));
+    drop(_mock_guard);
+
+    join_list(&app, "ursula@example.com", "weekly").await;
+    assert_eq!(membership_status(&app, "ursula@example.com", list_id).await.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn subscribers_can_join_several_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let weekly = create_list(&app, "Weekly").await;
+    let monthly = create_list(&app, "Monthly").await;
+
+    join_list(&app, "ursula@example.com", "weekly").await;
+    join_list(&app, "ursula@example.com", "monthly").await;
+
+    assert_eq!(membership_status(&app, "ursula@example.com", weekly).await.as_deref(), Some("confirmed"));
+    assert_eq!(membership_status(&app, "ursula@example.com", monthly).await.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    let response = subscribe(&app, "ursula@example.com", "nope").await;
+    assert_eq!(response.status().as_u16(),400);
+}
+
+#[tokio::test]
+async fn issues_go_once_to_the_members_of_their_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let weekly = create_list(&app, "Weekly").await;
+    let monthly = create_list(&app, "Monthly").await;
+    create_list(&app, "Yearly").await;
+    join_list(&app, "both@example.com", "weekly").await;
+    join_list(&app, "both@example.com", "monthly").await;
+    join_list(&app, "weekly@example.com", "weekly").await;
+    join_list(&app, "yearly@example.com", "yearly").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let weekly = weekly.to_string();
+    let monthly = monthly.to_string();
+    let body = [
+        ("title","Newsletter title"),
+        ("html_content","<p>Body</p>"),
+        ("text_content","Body"),
+        ("list_ids",&weekly),
+        ("list_ids",&monthly),
+        ("idempotency_key",&idempotency_key),
+    ];
+    assert!(app.post_preview(&body).await.contains("this issue would go to 2 subscribers."));
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    assert!(app.get_newsletter_html().await.contains("2 deliveries have been enqueued."));
+    app.dispatch_all_pending_email().await;
+
+    let mut recipients: Vec<_> = app
+        .batch_emails()
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_owned())
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, vec!["both@example.com", "weekly@example.com"]);
+}
+
+#[tokio::test]
+async fn issues_without_a_list_go_to_every_confirmed_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "Weekly").await;
+    join_list(&app, "weekly@example.com", "weekly").await;
+    crate::newsletter::create_confirmed_subscriber(&app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    app.post_newsletter(&body).await;
+    assert!(app.get_newsletter_html().await.contains("2 deliveries have been enqueued."));
+}
+
+#[tokio::test]
+async fn drafts_remember_their_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let weekly = create_list(&app, "Weekly").await.to_string();
+    create_list(&app, "Monthly").await;
+
+    let body = [
+        ("title","Draft Title"),
+        ("html_content","<p>Body</p>"),
+        ("text_content","Body"),
+        ("list_ids",weekly.as_str()),
+    ];
+    let response = app.post_draft("", &body).await;
+    let draft = response.headers()["Location"].to_str().unwrap().strip_prefix("/admin/drafts").unwrap().to_string();
+
+    let html = app.get_drafts_html(&draft).await;
+    assert!(html.contains(&format!(r#"value="{}" checked> Weekly"#, weekly)));
+    assert!(!html.contains("checked> Monthly"));
+}
+
+#[tokio::test]
+async fn lists_targeted_by_an_unsent_issue_cannot_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let weekly = create_list(&app, "Weekly").await;
+
+    let body = [
+        ("title","Draft Title"),
+        ("html_content","<p>Body</p>"),
+        ("text_content","Body"),
+        ("list_ids",&weekly.to_string()),
+    ];
+    app.post_draft("", &body).await;
+
+    let response = app.post_list_action(&weekly, "delete").await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    assert!(app.get_lists_html().await.contains("The list is still used by 1 issues that have not been sent yet."));
+    let n_targets = sqlx::query!("SELECT count(*) AS \"count!\" FROM newsletter_issue_lists WHERE list_id = $1", weekly)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_targets, 1);
+}
+
+#[tokio::test]
+async fn lists_of_sent_issues_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let weekly = create_list(&app, "Weekly").await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = [
+        ("title","Newsletter title"),
+        ("html_content","<p>Body</p>"),
+        ("text_content","Body"),
+        ("list_ids",&weekly.to_string()),
+        ("idempotency_key",&idempotency_key),
+    ];
+    app.post_newsletter(&body).await;
+
+    app.post_list_action(&weekly, "delete").await;
+    assert!(app.get_lists_html().await.contains("The list has been deleted."));
+    let n_lists = sqlx::query!("SELECT count(*) AS \"count!\" FROM lists")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_lists, 0);
+}
+
+#[tokio::test]
+async fn issues_sent_to_an_unknown_list_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    crate::newsletter::create_confirmed_subscriber(&app).await;
+
+    let unknown = Uuid::new_v4().to_string();
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = [
+        ("title","Newsletter title"),
+        ("html_content","<p>Body</p>"),
+        ("text_content","Body"),
+        ("list_ids",&unknown),
+        ("idempotency_key",&idempotency_key),
+    ];
+    assert!(app.post_preview(&body).await.contains("this issue would go to 0 subscribers."));
+    let response = app.post_newsletter(&body).await;
+    assert!(response.status().is_client_error());
+    let n_queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
@@ -4,7 +4,19 @@ mod helpers;
 mod subscriptions;
 mod health_check;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 mod newsletter;
+mod deliveries;
+mod drafts;
+mod archive;
+mod feeds;
+mod search;
+mod issues;
+mod scheduled_issues;
+mod templates;
+mod lists;
+mod segments;
+mod webhooks;
 mod login;
 mod reset;
 
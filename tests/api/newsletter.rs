@@ -1,9 +1,10 @@
-use std::time::Duration;
+use std::{collections::HashSet, time::Duration};
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
-use fake::{faker::{internet::pt_pt::SafeEmail, name::cy_gb::Name}, Fake};
+use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, PostmarkBatchResponder, TestApp};
+use fake::{faker::{internet::en::SafeEmail, name::en::Name}, Fake};
 use uuid::Uuid;
-use wiremock::{matchers::{any, method, path}, Mock, ResponseTemplate};
+use zero2production::issue_delivery_work::workers_loop;
+use wiremock::{matchers::{any, body_partial_json, method, path}, Mock, Request, Respond, ResponseTemplate};
 
 
 
@@ -103,7 +104,7 @@ async fn newsletter_returns_400_for_invalid_data() {
 //
 // }
 
-async fn create_unconfirmed_subscriber(app:&TestApp) -> ConfirmationLinks {
+pub async fn create_unconfirmed_subscriber(app:&TestApp) -> ConfirmationLinks {
     let name :String = Name().fake();
     let email:String = SafeEmail().fake();
 
@@ -137,7 +138,7 @@ async fn create_unconfirmed_subscriber(app:&TestApp) -> ConfirmationLinks {
 
 }
 
-async fn create_confirmed_subscriber(app:&TestApp) {
+pub async fn create_confirmed_subscriber(app:&TestApp) {
     let confirmation_links = create_unconfirmed_subscriber(app).await;
     reqwest::get(confirmation_links.html)
         .await
@@ -193,7 +194,7 @@ async fn succesfull_send_newsletter() {
 
     let newsletter_resp = app.post_newsletter(&body).await;
     assert_is_redirect_to(&newsletter_resp, "/admin/newsletter");
-    assert!(app.get_newsletter_html().await.contains("<p><i>Succesfully sent email to"));
+    assert!(app.get_newsletter_html().await.contains("0 deliveries have been enqueued."));
 }
 
 #[tokio::test]
@@ -226,7 +227,7 @@ async fn newsletter_creation_is_idempotent() {
     assert_is_redirect_to(&repsonse, "/admin/newsletter");
 
     let html_page = app.get_newsletter_html().await;
-    assert!(html_page.contains("<p><i>Succesfully sent email to"));
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!"));
 
     let response = app.post_newsletter(&newsletter_request_body).await;
     // assert_eq!(response.status().as_u16(),500);
@@ -269,10 +270,10 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
-        .expect(2)
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
         .mount(&app.email_server)
         .await;
 
@@ -304,32 +305,634 @@ async fn transient_error_do_not_cause_duplicate_on_delivery() {
         "idempotency_key":Uuid::new_v4().to_string()
     });
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(ResponseTemplate::new(500))
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
         .await;
-    Mock::given(path("/email"))
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    app.dispatch_all_pending_email().await;
+
+    // Submitting the form again must not enqueue the issue a second time.
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .named("Delivery retry")
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE issues_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_email().await;
+
+    let recipients: HashSet<_> = app
+        .batch_emails()
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(recipients.len(),2);
+}
+
+pub async fn publish_newsletter_to_confirmed_subscriber(app:&TestApp) {
+    create_confirmed_subscriber(app).await;
+    app.test_user.login(app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_with_backoff() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+
+    let failing = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+    drop(failing);
+
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"in_the_future!\" FROM issues_delivery_queue"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery should still be queued");
+    assert_eq!(task.n_retries,1);
+    assert!(task.in_the_future);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE issues_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_email().await;
+
+    let remaining = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count,0);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_moved_to_failures_after_max_retries() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+
+    sqlx::query!(
+        "UPDATE issues_delivery_queue SET n_retries = $1",
+        app.issue_delivery.max_retries as i32
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(500))
-        .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
         .await;
+    app.dispatch_all_pending_email().await;
+
+    let remaining = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count,0);
+
+    let failure = sqlx::query!("SELECT last_error, n_attempts FROM issues_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery should be recorded");
+    assert_eq!(failure.n_attempts,app.issue_delivery.max_retries as i32 + 1);
+    assert!(failure.last_error.contains("500"));
+}
+
+#[tokio::test]
+async fn invalid_stored_emails_are_moved_to_failures() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+
+    sqlx::query!("UPDATE issues_delivery_queue SET subscriber_email = 'not-an-email'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_email().await;
+
+    let failure = sqlx::query!("SELECT subscriber_email, n_attempts FROM issues_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The invalid delivery should be recorded");
+    assert_eq!(failure.subscriber_email,"not-an-email");
+    assert_eq!(failure.n_attempts,0);
+}
+
+#[tokio::test]
+async fn concurrent_workers_do_not_send_the_same_email_twice() {
+    let mut app = spawn_app().await;
+    // Small batches so that every worker gets a share of the queue.
+    app.issue_delivery.batch_size = 3;
+    let n_subscribers = 10;
+    for _ in 0..n_subscribers {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &Request| {
+            PostmarkBatchResponder
+                .respond(request)
+                .set_delay(Duration::from_millis(50))
+        })
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    tokio::join!(
+        app.dispatch_all_pending_email(),
+        app.dispatch_all_pending_email(),
+        app.dispatch_all_pending_email(),
+        app.dispatch_all_pending_email(),
+    );
+
+    let recipients: Vec<String> = app
+        .batch_emails()
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_owned())
+        .collect();
+    let unique: HashSet<_> = recipients.iter().collect();
+    assert_eq!(recipients.len(),n_subscribers);
+    assert_eq!(unique.len(),n_subscribers);
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_when_an_issue_is_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+
+    // With an hour-long poll interval only a NOTIFY can wake the worker up.
+    let mut issue_delivery = app.issue_delivery.clone();
+    issue_delivery.poll_interval_milliseconds = 3_600_000;
+    let (pool, email_client, base_url, hmac_secret) = (
+        app.db_pool.clone(),
+        app.email_client.clone(),
+        app.base_url.clone(),
+        app.hmac_secret.clone(),
+    );
+    tokio::spawn(async move {
+        workers_loop(&pool, &email_client, &base_url, &hmac_secret, &issue_delivery).await
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
     let response = app.post_newsletter(&body).await;
-    assert_eq!(response.status().as_u16(),500);
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let delivered = async {
+        loop {
+            if !app.batch_emails().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), delivered)
+        .await
+        .expect("The worker did not pick up the new issue");
+}
+
+#[tokio::test]
+async fn only_recipients_rejected_by_a_batch_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    // Postmark rejects the first message of the batch and accepts the rest.
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &Request| {
+            let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = messages
+                .iter()
+                .enumerate()
+                .map(|(i, m)| match i {
+                    0 => serde_json::json!({"ErrorCode": 406, "Message": "Inactive recipient", "To": m["To"]}),
+                    _ => serde_json::json!({"ErrorCode": 0, "Message": "OK", "To": m["To"]}),
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let emails = app.batch_emails().await;
+    assert_eq!(emails.len(),2);
+    let task = sqlx::query!("SELECT subscriber_email, n_retries FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rejected delivery should still be queued");
+    assert_eq!(task.subscriber_email,emails[0]["To"].as_str().unwrap());
+    assert_eq!(task.n_retries,1);
+
+    let delivery = sqlx::query!(
+        "SELECT status, error_code FROM deliveries WHERE subscriber_email = $1",
+        task.subscriber_email
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rejected delivery should be recorded");
+    assert_eq!(delivery.status,"retrying");
+    assert_eq!(delivery.error_code,Some(406));
+}
+
+#[tokio::test]
+async fn deliveries_are_recorded_with_the_provider_message_id() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d"}
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let email = app.batch_emails().await.pop().unwrap();
+    let delivery = sqlx::query!(
+        "SELECT subscriber_email, provider_message_id, status, error_code FROM deliveries"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should be recorded");
+    assert_eq!(delivery.subscriber_email,email["To"].as_str().unwrap());
+    assert_eq!(delivery.provider_message_id.as_deref(),Some("0a129aee-e1cd-480d-b08d-4f48548ff48d"));
+    assert_eq!(delivery.status,"sent");
+    assert_eq!(delivery.error_code,None);
+}
+
+#[tokio::test]
+async fn publishing_only_enqueues_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let issue = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("2 deliveries have been enqueued."));
+    assert!(html_page.contains(&format!(
+        "<a href=\"/admin/issues/{}\">",
+        issue.newsletter_issues_id
+    )));
+}
+
+fn test_email_body(test_recipients:&str) -> serde_json::Value {
+    serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "test_recipients":test_recipients,
+    })
+}
+
+#[tokio::test]
+async fn test_emails_are_sent_without_publishing_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    for recipient in ["editor@example.com", "reviewer@example.com"] {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "To": recipient,
+                "Subject": "[TEST] Newsletter Title",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&app.email_server)
+            .await;
+    }
+
+    let response = app.post_test_email(&test_email_body("editor@example.com, reviewer@example.com")).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("A test email has been sent to editor@example.com, reviewer@example.com."));
+
+    let n_issues = sqlx::query!("SELECT count(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues,0);
+    let n_queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued,0);
+}
+
+#[tokio::test]
+async fn test_emails_are_rendered_like_the_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
 
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .named("Delivery retry")
         .expect(1)
         .mount(&app.email_server)
         .await;
 
+    app.post_test_email(&test_email_body("editor@example.com")).await;
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let text_body = email["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Newsletter body as text\n\nUnsubscribe from this newsletter: "));
+    assert!(text_body.contains("email=editor%40example.com"));
+    assert!(email["HtmlBody"].as_str().unwrap().contains(">Unsubscribe</a> from this newsletter."));
+    let headers = email["Headers"].as_array().unwrap();
+    assert_eq!(headers[0]["Name"],"List-Unsubscribe");
+    assert!(headers[0]["Value"].as_str().unwrap().starts_with("<http"));
+    assert_eq!(headers[1]["Name"],"List-Unsubscribe-Post");
+    assert_eq!(headers[1]["Value"],"List-Unsubscribe=One-Click");
+}
+
+#[tokio::test]
+async fn test_emails_are_not_sent_to_invalid_addresses() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_test_email(&test_email_body("editor@example.com, not-an-email")).await;
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("not-an-email is not a valid subscriber email!"));
+
+    app.post_test_email(&test_email_body(" ")).await;
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("Enter at least one address to send the test email to."));
+}
+
+#[tokio::test]
+async fn failed_test_emails_are_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_test_email(&test_email_body("editor@example.com")).await;
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("Failed to send the test email to editor@example.com."));
+}
+
+#[tokio::test]
+async fn the_preview_renders_both_parts_for_a_sample_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html = app.post_preview(&serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p>Hello & welcome</p>",
+        "text_content":"Hello & welcome",
+    })).await;
+
+    assert!(html.contains("No problem found."));
+    assert!(html.contains(r#"<iframe sandbox"#));
+    assert!(html.contains("&lt;p&gt;Hello&#x20;&amp;&#x20;welcome&lt;&#x2F;p&gt;"));
+    assert!(html.contains("Hello &amp; welcome\n\nUnsubscribe from this newsletter: "));
+    assert!(html.contains("email=subscriber%40example.com"));
+}
+
+#[tokio::test]
+async fn the_preview_highlights_problems() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html = app.post_preview(&serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<div><b>Hello</div>",
+        "text_content":" ",
+    })).await;
+
+    assert!(html.contains("<li>The plain-text part is empty.</li>"));
+    assert!(html.contains("<li>&lt;b&gt; is never closed.</li>"));
+}
+
+#[tokio::test]
+async fn markdown_issues_are_rendered_to_html_and_text() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "markdown_content":"# Hello\n\nThis is **bold** <script>",
+        "html_content":"",
+        "text_content":"",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
     let response = app.post_newsletter(&body).await;
-    assert_eq!(response.status().as_u16(),303);
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let issue = sqlx::query!("SELECT markdown_content, html_content, text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.markdown_content,"# Hello\n\nThis is **bold** <script>");
+    assert_eq!(issue.html_content,"<h1>Hello</h1>\n<p>This is <strong>bold</strong> </p>");
+    assert_eq!(issue.text_content,"Hello\n=====\n\nThis is bold");
+}
+
+#[tokio::test]
+async fn hand_written_parts_override_the_markdown() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "markdown_content":"*Rendered*",
+        "html_content":"<p>Hand written</p>",
+        "text_content":"",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    app.post_newsletter(&body).await;
+
+    let issue = sqlx::query!("SELECT html_content, text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.html_content,"<p>Hand written</p>");
+    assert_eq!(issue.text_content,"Rendered");
+}
+
+#[tokio::test]
+async fn merge_tags_are_rendered_for_every_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET name = '<Ursula>'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "markdown_content":"Hi {{ subscriber.name }}, this is {{ issue.title }}.",
+        "html_content":"",
+        "text_content":"Hi {{ subscriber.name | default: \"friend\" }} <{{ subscriber.email }}>",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
     app.dispatch_all_pending_email().await;
+
+    let emails = app.batch_emails().await;
+    assert_eq!(emails.len(),2);
+    for email in &emails {
+        let recipient = email["To"].as_str().unwrap();
+        assert!(email["HtmlBody"].as_str().unwrap().starts_with("<p>Hi &lt;Ursula&gt;, this is Newsletter Title.</p>"));
+        assert!(email["TextBody"].as_str().unwrap().starts_with(&format!("Hi <Ursula> <{}>", recipient)));
+    }
+}
+
+#[tokio::test]
+async fn issues_with_unknown_merge_tags_are_kept_as_drafts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p>Hi {{ subscriber.age }}</p>",
+        "text_content":"Hi",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+
+    let html = app.get_newsletter_html().await;
+    assert!(html.contains("The newsletter issue has been kept as a draft: {{ subscriber.age }} is not a known merge tag."));
+    let n_enqueued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_enqueued,0);
+    let state = sqlx::query!("SELECT delivery_state FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .delivery_state;
+    assert_eq!(state,"draft");
+}
+
+#[tokio::test]
+async fn the_preview_fills_in_merge_tags_for_the_sample_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html = app.post_preview(&serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p>Hi</p>",
+        "text_content":"Hi {{ subscriber.name }} {{ subscriber.age }}",
+    })).await;
+
+    assert!(html.contains("Hi Sample Subscriber {{ subscriber.age }}"));
+    assert!(html.contains("<li>{{ subscriber.age }} is not a known merge tag.</li>"));
 }
@@ -0,0 +1,126 @@
+use uuid::Uuid;
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, TestApp}, newsletter::create_confirmed_subscriber};
+
+
+async fn schedule_newsletter(app:&TestApp, scheduled_for:&str) -> reqwest::Response {
+    app.test_user.login(app).await;
+    let body = serde_json::json!({
+        "title":"Scheduled Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string(),
+        "scheduled_for":scheduled_for,
+    });
+    app.post_newsletter(&body).await
+}
+
+async fn scheduled_issue_id(app:&TestApp) -> String {
+    sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("No newsletter issue was scheduled")
+        .newsletter_issues_id
+        .to_string()
+}
+
+async fn queued_deliveries(app:&TestApp) -> i64 {
+    sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn make_due(app:&TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_enqueued_once_they_are_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = schedule_newsletter(&app, "2999-01-01T09:30").await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html = app.get_newsletter_html().await;
+    assert!(html.contains("The newsletter issue has been scheduled for 2999-01-01 09:30 UTC."));
+
+    app.publish_due_issues().await;
+    assert_eq!(queued_deliveries(&app).await,0);
+    let html = app.get_scheduled_issues_html().await;
+    assert!(html.contains("<td>Scheduled Title</td><td>2999-01-01 09:30</td>"));
+
+    make_due(&app).await;
+    app.publish_due_issues().await;
+    assert_eq!(queued_deliveries(&app).await,1);
+    let issue = sqlx::query!("SELECT delivery_state, published_at IS NOT NULL AS \"published!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.delivery_state,"sending");
+    assert!(issue.published);
+    assert!(app.get_scheduled_issues_html().await.contains("No issue is scheduled."));
+}
+
+#[tokio::test]
+async fn invalid_send_times_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    for scheduled_for in ["2020-01-01T09:30", "next tuesday"] {
+        let response = schedule_newsletter(&app, scheduled_for).await;
+        assert!(response.status().is_client_error(),"{} was accepted",scheduled_for);
+    }
+    let n_issues = sqlx::query!("SELECT count(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues,0);
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    schedule_newsletter(&app, "2999-01-01T09:30").await;
+    let issue_id = scheduled_issue_id(&app).await;
+
+    let response = app.post_reschedule_issue(&issue_id, "2999-02-01T18:00").await;
+    assert_is_redirect_to(&response, "/admin/issues/scheduled");
+    let html = app.get_scheduled_issues_html().await;
+    assert!(html.contains("The issue has been rescheduled for 2999-02-01 18:00 UTC."));
+    assert!(html.contains("<td>2999-02-01 18:00</td>"));
+
+    app.post_reschedule_issue(&issue_id, "2020-01-01T09:30").await;
+    let html = app.get_scheduled_issues_html().await;
+    assert!(html.contains("The send time must be in the future."));
+    assert!(html.contains("<td>2999-02-01 18:00</td>"));
+
+    app.post_reschedule_issue(&issue_id, "<script>alert(1)</script>").await;
+    let html = app.get_scheduled_issues_html().await;
+    assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt; is not a valid send time."));
+    assert!(!html.contains("<script>"));
+}
+
+#[tokio::test]
+async fn cancelled_scheduled_issues_are_never_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    schedule_newsletter(&app, "2999-01-01T09:30").await;
+    let issue_id = scheduled_issue_id(&app).await;
+
+    let response = app.post_issue_action(&issue_id, "unschedule").await;
+    assert_is_redirect_to(&response, "/admin/issues/scheduled");
+    let html = app.get_scheduled_issues_html().await;
+    assert!(html.contains("The scheduled issue has been cancelled."));
+    assert!(html.contains("No issue is scheduled."));
+
+    make_due(&app).await;
+    app.publish_due_issues().await;
+    assert_eq!(queued_deliveries(&app).await,0);
+}
@@ -0,0 +1,62 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+
+async fn publish_issue(app:&TestApp, title:&str, text_content:&str) {
+    let body = serde_json::json!({
+        "title":title,
+        "html_content":"<p>Body</p>",
+        "text_content":text_content,
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+#[tokio::test]
+async fn the_archive_search_ranks_and_highlights_matches() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Gardening tips", "Water your tomatoes in the morning.").await;
+    publish_issue(&app, "Tomatoes", "All about tomatoes: sowing, watering and <pruning> tomatoes.").await;
+    publish_issue(&app, "Cooking", "Nothing about the red fruit here.").await;
+
+    let html = app.get_archive_html("/search?q=tomato").await;
+    let best = html.find(r#"<a href="/archive/tomatoes">Tomatoes</a>"#).unwrap();
+    let other = html.find(r#"<a href="/archive/gardening-tips">Gardening tips</a>"#).unwrap();
+    assert!(best < other);
+    assert!(!html.contains("Cooking"));
+    assert!(html.contains("<mark>tomatoes</mark>"));
+    assert!(!html.contains("<pruning>"));
+}
+
+#[tokio::test]
+async fn the_archive_search_only_finds_public_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_draft("", &serde_json::json!({
+        "title":"Secret draft",
+        "html_content":"<p>Draft</p>",
+        "text_content":"An unannounced product.",
+    })).await;
+
+    let html = app.get_archive_html("/search?q=unannounced").await;
+    assert!(html.contains("No issue matches your search."));
+
+    let html = app.get_admin_search_html("unannounced").await;
+    assert!(html.contains("Secret draft"));
+    assert!(html.contains("<mark>unannounced</mark>"));
+    assert!(html.contains(r#"<a href="/admin/drafts/"#));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_search_every_issue() {
+    let app = spawn_app().await;
+    let response = app.api_client
+        .get(format!("{}/admin/search?q=x",&app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
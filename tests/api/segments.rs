@@ -0,0 +1,201 @@
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp};
+
+
+/// Subscribes `email`, signing up through `source`, and confirms it.
+async fn create_confirmed_subscriber(app:&TestApp, email:&str, source:&str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("name","Le Guin"),("email",email),("source",source)]).unwrap();
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmations_link(&email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+}
+
+async fn create_segment(app:&TestApp, body:&serde_json::Value) -> Uuid {
+    let response = app.post_segment("", body).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    sqlx::query!("SELECT segment_id FROM segments WHERE name = $1", body["name"].as_str().unwrap())
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The segment was not created")
+        .segment_id
+}
+
+async fn tag_id(app:&TestApp, name:&str) -> Uuid {
+    sqlx::query!("SELECT tag_id FROM tags WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .tag_id
+}
+
+async fn recipient_count_in_preview(app:&TestApp, segment_id:&str) -> String {
+    app.post_preview(&serde_json::json!({
+        "title":"Newsletter title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "segment_id":segment_id
+    })).await
+}
+
+#[tokio::test]
+async fn subscribers_can_be_tagged_and_untagged() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app, "ursula@example.com", "").await;
+    create_confirmed_subscriber(&app, "octavia@example.com", "").await;
+
+    let response = app.post_tags("", &serde_json::json!({
+        "name":"vip",
+        "emails":"ursula@example.com, octavia@example.com\nnobody@example.com"
+    })).await;
+    assert_is_redirect_to(&response, "/admin/tags");
+    let html = app.get_tags_html().await;
+    assert!(html.contains("2 subscribers have been tagged vip."));
+    assert!(html.contains("No subscriber uses nobody@example.com."));
+    assert!(html.contains("<tr><td>vip</td><td>2</td></tr>"));
+
+    app.post_tags("/remove", &serde_json::json!({"name":"vip","emails":"octavia@example.com"})).await;
+    let html = app.get_tags_html().await;
+    assert!(html.contains("The tag vip has been removed from 1 subscribers."));
+    assert!(html.contains("<tr><td>vip</td><td>1</td></tr>"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_tag_segment_only_reach_tagged_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app, "ursula@example.com", "").await;
+    create_confirmed_subscriber(&app, "octavia@example.com", "").await;
+    app.post_tags("", &serde_json::json!({"name":"vip","emails":"ursula@example.com"})).await;
+    let tag_id = tag_id(&app, "vip").await;
+    let segment_id = create_segment(&app, &serde_json::json!({"name":"VIPs","tag_id":tag_id})).await;
+    assert!(app.get_segments_html().await.contains("<td>VIPs</td><td>tagged vip</td><td>1</td>"));
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletter(&serde_json::json!({
+        "title":"Newsletter title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "segment_id":segment_id,
+        "idempotency_key":Uuid::new_v4().to_string()
+    })).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    assert!(app.get_newsletter_html().await.contains("1 deliveries have been enqueued."));
+    app.dispatch_all_pending_email().await;
+
+    let recipients: Vec<_> = app
+        .batch_emails()
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(recipients, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn segments_filter_on_signup_source_and_dates() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app, "blog@example.com", "blog").await;
+    create_confirmed_subscriber(&app, "podcast@example.com", "podcast").await;
+    create_confirmed_subscriber(&app, "old@example.com", "blog").await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = '2020-01-31T23:30:00Z' WHERE email = 'old@example.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let from_blog = create_segment(&app, &serde_json::json!({"name":"Blog","signup_source":"blog"})).await;
+    let january = create_segment(&app, &serde_json::json!({
+        "name":"January 2020",
+        "signed_up_from":"2020-01-01",
+        "signed_up_until":"2020-01-31"
+    })).await;
+    let recent_blog = create_segment(&app, &serde_json::json!({
+        "name":"Recent blog",
+        "signed_up_from":"2020-02-01",
+        "signup_source":"blog"
+    })).await;
+
+    assert!(recipient_count_in_preview(&app, &from_blog.to_string()).await.contains("this issue would go to 2 subscribers."));
+    assert!(recipient_count_in_preview(&app, &january.to_string()).await.contains("this issue would go to 1 subscribers."));
+    assert!(recipient_count_in_preview(&app, &recent_blog.to_string()).await.contains("this issue would go to 1 subscribers."));
+    assert!(recipient_count_in_preview(&app, "").await.contains("this issue would go to 3 subscribers."));
+}
+
+#[tokio::test]
+async fn segments_need_a_valid_condition() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_segment("", &serde_json::json!({"name":"Everyone"})).await;
+    assert!(app.get_segments_html().await.contains("A segment needs at least one condition."));
+
+    app.post_segment("", &serde_json::json!({
+        "name":"Backwards",
+        "signed_up_from":"2026-02-01",
+        "signed_up_until":"2026-01-01"
+    })).await;
+    assert!(app.get_segments_html().await.contains("The signup range ends before it starts."));
+
+    app.post_segment("", &serde_json::json!({"name":"Ghost tag","tag_id":Uuid::new_v4()})).await;
+    assert!(app.get_segments_html().await.contains("The tag does not exist."));
+}
+
+#[tokio::test]
+async fn drafts_keep_their_segment_and_block_its_deletion() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let segment_id = create_segment(&app, &serde_json::json!({"name":"Blog","signup_source":"blog"})).await;
+
+    let response = app.post_draft("", &serde_json::json!({
+        "title":"Draft Title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "segment_id":segment_id
+    })).await;
+    let draft = response.headers()["Location"].to_str().unwrap().strip_prefix("/admin/drafts").unwrap().to_string();
+    let html = app.get_drafts_html(&draft).await;
+    assert!(html.contains(&format!(r#"<option value="{}" selected>Blog</option>"#, segment_id)));
+
+    let response = app.post_segment(&format!("/{}/delete", segment_id), &serde_json::json!({})).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    assert!(app.get_segments_html().await.contains("The segment is still used by 1 issues that have not been sent yet."));
+}
+
+#[tokio::test]
+async fn segments_of_sent_issues_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let segment_id = create_segment(&app, &serde_json::json!({"name":"Blog","signup_source":"blog"})).await;
+    app.post_newsletter(&serde_json::json!({
+        "title":"Newsletter title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "segment_id":segment_id,
+        "idempotency_key":Uuid::new_v4().to_string()
+    })).await;
+
+    app.post_segment(&format!("/{}/delete", segment_id), &serde_json::json!({})).await;
+    assert!(app.get_segments_html().await.contains("The segment has been deleted."));
+
+    let response = app.post_newsletter(&serde_json::json!({
+        "title":"Another title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "segment_id":segment_id,
+        "idempotency_key":Uuid::new_v4().to_string()
+    })).await;
+    assert!(response.status().is_client_error());
+}
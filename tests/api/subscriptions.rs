@@ -140,6 +140,43 @@ async fn subscribe_fail_if_theres_database_error() {
     assert_eq!(response.status().as_u16(),500);
 }
 
+#[tokio::test]
+async fn complained_addresses_are_not_mailed_when_they_subscribe_again() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmations_link(email_request);
+
+    sqlx::query!("UPDATE subscriptions SET status = 'complained'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "INSERT INTO email_suppressions (email, reason, suppressed_at) VALUES ('ursula_le_guin@gmail.com', 'complained', now())"
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(),200);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status,"complained");
+}
+
 
 async fn create_unconfirmed_subscriber(app:&TestApp) {
     let body = "name=billy%20bongso&email=billybongso2001%40gmail.com";
@@ -0,0 +1,131 @@
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock};
+use zero2production::routes::unsubscribe_link;
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp}, newsletter::create_confirmed_subscriber};
+
+
+async fn subscriber_status(app:&TestApp) -> (String,String) {
+    let row = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    (row.email,row.status)
+}
+
+#[tokio::test]
+async fn unsubscribe_link_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let link = unsubscribe_link(&app.address, &email, &app.hmac_secret);
+
+    let form = app.api_client.get(&link).send().await.unwrap();
+    assert_eq!(form.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"confirmed");
+
+    let response = app.api_client.post(&link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribe_link_with_a_tampered_tag_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let link = unsubscribe_link(&app.address, "someone-else@example.com", &app.hmac_secret);
+    let tag = link.split("tag=").nth(1).unwrap();
+    let forged = format!(
+        "{}/subscriptions/unsubscribe?email={}&tag={}",
+        app.address,
+        urlencoding::encode(&email),
+        tag
+    );
+
+    let response = app.api_client.post(&forged).send().await.unwrap();
+    assert_eq!(response.status().as_u16(),401);
+    assert_eq!(subscriber_status(&app).await.1,"confirmed");
+}
+
+#[tokio::test]
+async fn newsletter_emails_contain_a_working_unsubscribe_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    app.dispatch_all_pending_email().await;
+
+    let email = app.batch_emails().await.pop().unwrap();
+    let link = app.get_unsubscribe_link(&email);
+
+    let response = app.api_client.post(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"unsubscribed");
+}
+
+#[tokio::test]
+async fn newsletter_emails_carry_one_click_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    app.dispatch_all_pending_email().await;
+
+    let email = app.batch_emails().await.pop().unwrap();
+    let headers = email["Headers"].as_array().unwrap();
+    let header = |name:&str| headers
+        .iter()
+        .find(|h| h["Name"] == name)
+        .and_then(|h| h["Value"].as_str())
+        .unwrap()
+        .to_owned();
+    assert_eq!(header("List-Unsubscribe-Post"),"List-Unsubscribe=One-Click");
+
+    let list_unsubscribe = header("List-Unsubscribe");
+    let mut link = reqwest::Url::parse(
+        list_unsubscribe.trim_start_matches('<').trim_end_matches('>')
+    ).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    // Mailbox providers send the one-click POST without any session.
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"unsubscribed");
+}
@@ -0,0 +1,174 @@
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock};
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp}, newsletter::create_confirmed_subscriber};
+
+
+fn template_body(name:&str) -> serde_json::Value {
+    serde_json::json!({
+        "name":name,
+        "html_layout":"<div class=\"brand\">{{content}}</div><footer>Footer</footer>",
+        "text_layout":"Brand\n{{content}}\n-- Footer",
+    })
+}
+
+async fn create_template(app:&TestApp, name:&str) -> Uuid {
+    let response = app.post_template("", &template_body(name)).await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    sqlx::query!("SELECT email_template_id FROM email_templates WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email_template_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_templates() {
+    let app = spawn_app().await;
+    let response = app.api_client
+        .get(format!("{}/admin/templates",&app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn templates_can_be_created_and_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let template_id = create_template(&app, "Brand").await;
+    let html = app.get_templates_html("").await;
+    assert!(html.contains("The template has been saved."));
+    assert!(html.contains(&format!(r#"<a href="/admin/templates/{}">Brand</a>"#, template_id)));
+
+    let response = app.post_template(&format!("/{}", template_id), &template_body("Brand v2")).await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    assert!(app.get_templates_html("").await.contains("Brand v2"));
+    assert!(app.get_templates_html(&format!("/{}", template_id)).await.contains("&lt;footer&gt;Footer&lt;/footer&gt;"));
+}
+
+#[tokio::test]
+async fn templates_without_a_content_slot_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let body = serde_json::json!({
+        "name":"Broken",
+        "html_layout":"<div>No slot</div>",
+        "text_layout":"{{content}}",
+    });
+    let response = app.post_template("", &body).await;
+    assert_is_redirect_to(&response, "/admin/templates/new");
+    assert!(app.get_templates_html("/new").await.contains("The HTML layout has no {{content}} slot."));
+    assert!(app.get_templates_html("").await.contains("There are no templates."));
+}
+
+#[tokio::test]
+async fn template_names_are_unique() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_template(&app, "Brand").await;
+
+    let response = app.post_template("", &template_body("Brand")).await;
+    assert_is_redirect_to(&response, "/admin/templates/new");
+    assert!(app.get_templates_html("/new").await.contains("A template named Brand already exists."));
+}
+
+#[tokio::test]
+async fn issues_are_delivered_wrapped_in_their_template() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let template_id = create_template(&app, "Brand").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content":"Newsletter body as plain text",
+        "html_content":"<p>Newsletter body as HTML</p>",
+        "email_template_id":template_id.to_string(),
+        "idempotency_key":Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    app.dispatch_all_pending_email().await;
+
+    let emails = app.batch_emails().await;
+    assert_eq!(emails.len(),1);
+    let html_body = emails[0]["HtmlBody"].as_str().unwrap();
+    let text_body = emails[0]["TextBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<div class=\"brand\"><p>Newsletter body as HTML</p>"));
+    assert!(html_body.contains("<footer>Footer</footer>"));
+    assert!(text_body.starts_with("Brand\nNewsletter body as plain text"));
+    assert!(text_body.contains("-- Footer"));
+}
+
+#[tokio::test]
+async fn templates_used_by_a_draft_cannot_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let template_id = create_template(&app, "Brand").await;
+
+    let draft = serde_json::json!({
+        "title":"Draft Title",
+        "html_content":"<p> Draft body as HTML </p>",
+        "text_content":"Draft body as text",
+        "email_template_id":template_id.to_string(),
+    });
+    app.post_draft("", &draft).await;
+
+    let response = app.post_template(&format!("/{}/delete", template_id), &serde_json::json!({})).await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    let html = app.get_templates_html("").await;
+    assert!(html.contains("The template is still used by 1 issues that have not been sent yet."));
+
+    sqlx::query!("DELETE FROM newsletter_issues")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_template(&format!("/{}/delete", template_id), &serde_json::json!({})).await;
+    let html = app.get_templates_html("").await;
+    assert!(html.contains("The template has been deleted."));
+    assert!(html.contains("There are no templates."));
+}
+
+#[tokio::test]
+async fn templates_of_sent_issues_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let template_id = create_template(&app, "Brand").await;
+    app.post_newsletter(&serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "email_template_id":template_id.to_string(),
+        "idempotency_key":Uuid::new_v4().to_string()
+    })).await;
+    sqlx::query!("UPDATE newsletter_issues SET delivery_state = 'completed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.post_template(&format!("/{}/delete", template_id), &serde_json::json!({})).await;
+    assert!(app.get_templates_html("").await.contains("The template has been deleted."));
+    let issue = sqlx::query!("SELECT email_template_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.email_template_id,None);
+
+    let response = app.post_draft("", &serde_json::json!({
+        "title":"Draft Title",
+        "html_content":"<p>Body</p>",
+        "text_content":"Body",
+        "email_template_id":template_id.to_string(),
+    })).await;
+    assert!(response.status().is_client_error());
+}
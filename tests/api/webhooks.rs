@@ -0,0 +1,188 @@
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+
+use crate::{helpers::{assert_is_redirect_to, spawn_app, TestApp}, newsletter::{create_confirmed_subscriber, publish_newsletter_to_confirmed_subscriber}};
+
+
+async fn subscriber_status(app:&TestApp) -> (String,String) {
+    let row = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    (row.email,row.status)
+}
+
+fn bounce(email:&str, bounce_type:&str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": bounce_type,
+        "MessageID": Uuid::new_v4().to_string(),
+        "Email": email,
+    })
+}
+
+async fn queued_deliveries_after_publishing(app:&TestApp) -> i64 {
+    app.test_user.login(app).await;
+    let body = serde_json::json!({
+        "title":"Newsletter Title",
+        "html_content":"<p> Newsletter body as HTML </p>",
+        "text_content":"Newsletter body as text",
+        "idempotency_key":Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn events_without_valid_credentials_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let response = app.api_client
+        .post(format!("{}/webhooks/email-provider",&app.address))
+        .basic_auth(&app.email_webhook.username, Some("not-the-secret"))
+        .json(&bounce(&email, "HardBounce"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(),401);
+    assert_eq!(response.headers()["WWW-Authenticate"],r#"Basic realm="webhooks""#);
+    assert_eq!(subscriber_status(&app).await.1,"confirmed");
+}
+
+#[tokio::test]
+async fn hard_bounces_suppress_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let response = app.post_email_provider_event(&bounce(&email, "HardBounce")).await;
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"bounced");
+
+    let suppression = sqlx::query!("SELECT email, reason FROM email_suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The subscriber should be suppressed");
+    assert_eq!(suppression.email,email);
+    assert_eq!(suppression.reason,"hard_bounce");
+
+    assert_eq!(queued_deliveries_after_publishing(&app).await,0);
+}
+
+#[tokio::test]
+async fn spam_complaints_suppress_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let complaint = serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "MessageID": Uuid::new_v4().to_string(),
+        "Email": email,
+    });
+    let response = app.post_email_provider_event(&complaint).await;
+    assert_eq!(response.status().as_u16(),200);
+    assert_eq!(subscriber_status(&app).await.1,"complained");
+
+    assert_eq!(queued_deliveries_after_publishing(&app).await,0);
+}
+
+#[tokio::test]
+async fn soft_bounces_only_suppress_once_the_threshold_is_reached() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    for _ in 1..app.email_webhook.soft_bounce_threshold {
+        let response = app.post_email_provider_event(&bounce(&email, "SoftBounce")).await;
+        assert_eq!(response.status().as_u16(),200);
+    }
+    assert_eq!(subscriber_status(&app).await.1,"confirmed");
+
+    app.post_email_provider_event(&bounce(&email, "SoftBounce")).await;
+    assert_eq!(subscriber_status(&app).await.1,"bounced");
+    let suppression = sqlx::query!("SELECT reason FROM email_suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The subscriber should be suppressed");
+    assert_eq!(suppression.reason,"soft_bounces");
+}
+
+#[tokio::test]
+async fn bounces_update_the_status_of_the_matching_delivery() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+
+    let message_id = Uuid::new_v4().to_string();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": message_id}
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let mut event = bounce(&email, "HardBounce");
+    event["MessageID"] = message_id.into();
+    app.post_email_provider_event(&event).await;
+
+    let delivery = sqlx::query!("SELECT status FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status,"bounced");
+}
+
+#[tokio::test]
+async fn deliveries_queued_before_a_suppression_are_dropped() {
+    let app = spawn_app().await;
+    publish_newsletter_to_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    app.post_email_provider_event(&bounce(&email, "HardBounce")).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_email().await;
+
+    let n_queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued,0);
+    let issue = sqlx::query!("SELECT delivery_state FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.delivery_state,"completed");
+}
+
+#[tokio::test]
+async fn redelivered_soft_bounces_are_only_counted_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email,_) = subscriber_status(&app).await;
+
+    let event = bounce(&email, "SoftBounce");
+    for _ in 0..app.email_webhook.soft_bounce_threshold {
+        let response = app.post_email_provider_event(&event).await;
+        assert_eq!(response.status().as_u16(),200);
+    }
+    assert_eq!(subscriber_status(&app).await.1,"confirmed");
+}